@@ -0,0 +1,56 @@
+//! Raw emulation speed, without any rendering. Run with `cargo bench`. Criterion reports the
+//! time per step and, through the throughput, steps per second.
+
+use chip8::keyboard::Keyboard;
+use chip8::machine::Machine;
+use chip8::quirks::Quirks;
+use chip8::screen::Screen;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+/// Register arithmetic in a tight loop.
+const ARITHMETIC: &[u8] = &[
+    0x70, 0x01, // 200: ADD V0, 1
+    0x81, 0x04, // 202: ADD V1, V0
+    0x82, 0x15, // 204: SUB V2, V1
+    0x83, 0x26, // 206: SHR V3, V2
+    0x84, 0x3e, // 208: SHL V4, V3
+    0x85, 0x43, // 20A: XOR V5, V4
+    0x30, 0x00, // 20C: SE V0, 0
+    0x12, 0x00, // 20E: JP 0x200
+    0x12, 0x00, // 210: JP 0x200
+];
+
+/// Draws the 0 glyph across the screen, one sprite every other instruction.
+const DRAWING: &[u8] = &[
+    0xa0, 0x50, // 200: LD I, 0x050
+    0xd0, 0x15, // 202: DRW V0, V1, 5
+    0x70, 0x05, // 204: ADD V0, 5
+    0xd0, 0x15, // 206: DRW V0, V1, 5
+    0x71, 0x03, // 208: ADD V1, 3
+    0x12, 0x02, // 20A: JP 0x202
+];
+
+fn bench_program(c: &mut Criterion, name: &str, program: &[u8], decode_cache: bool) {
+    let mut screen = Screen::new();
+    let keyboard = Keyboard::new();
+    let mut machine = Machine::new(Quirks::default());
+    machine.set_decode_cache(decode_cache);
+    machine.load(program).unwrap();
+
+    let mut group = c.benchmark_group("step");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function(name, |b| {
+        b.iter(|| black_box(machine.step(&keyboard, &mut screen)))
+    });
+    group.finish();
+}
+
+fn bench_step(c: &mut Criterion) {
+    bench_program(c, "arithmetic", ARITHMETIC, false);
+    bench_program(c, "arithmetic cached", ARITHMETIC, true);
+    bench_program(c, "drawing", DRAWING, false);
+    bench_program(c, "drawing cached", DRAWING, true);
+}
+
+criterion_group!(benches, bench_step);
+criterion_main!(benches);
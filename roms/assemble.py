@@ -0,0 +1,130 @@
+# Assembles the opcode, quirks and keypad test ROMs: python3 roms/assemble.py roms
+# Each item is either a label string ending in ':' or a (op, *args) tuple, args may be labels.
+def assemble(prog, origin=0x200):
+    labels, addr = {}, origin
+    for item in prog:
+        if isinstance(item, str):
+            labels[item[:-1]] = addr
+        elif item[0] == 'bytes':
+            addr += len(item[1])
+        else:
+            addr += 2
+    out = bytearray()
+    for item in prog:
+        if isinstance(item, str):
+            continue
+        if item[0] == 'bytes':
+            out += bytes(item[1]); continue
+        op, *args = item
+        args = [labels[a] if isinstance(a, str) else a for a in args]
+        word = op(*args)
+        out += word.to_bytes(2, 'big')
+    return bytes(out), labels
+
+CLS = lambda: 0x00e0
+RET = lambda: 0x00ee
+JP = lambda a: 0x1000 | a
+CALL = lambda a: 0x2000 | a
+SE = lambda x, k: 0x3000 | x << 8 | k
+SNE = lambda x, k: 0x4000 | x << 8 | k
+SEV = lambda x, y: 0x5000 | x << 8 | y << 4
+LD = lambda x, k: 0x6000 | x << 8 | k
+ADD = lambda x, k: 0x7000 | x << 8 | k
+ALU = lambda x, y, n: 0x8000 | x << 8 | y << 4 | n
+SNEV = lambda x, y: 0x9000 | x << 8 | y << 4
+LDI = lambda a: 0xa000 | a
+JPV0 = lambda a: 0xb000 | a
+DRW = lambda x, y, n: 0xd000 | x << 8 | y << 4 | n
+FX = lambda x, n: 0xf000 | x << 8 | n
+
+def check(x, k):
+    # Fail unless Vx == k.
+    return [(SE, x, k), (JP, 'fail')]
+
+def passed():
+    return [(ADD, 5, 1)]
+
+opcode = [
+    (CLS,), (LD, 5, 0),
+    # 1: 7xkk
+    (LD, 0, 5), (ADD, 0, 3), *check(0, 8), *passed(),
+    # 2: 8xy4 with carry
+    (LD, 0, 0xff), (LD, 1, 2), (ALU, 0, 1, 4), *check(0, 1), *check(0xf, 1), *passed(),
+    # 3: 8xy5 with borrow
+    (LD, 0, 5), (LD, 1, 7), (ALU, 0, 1, 5), *check(0, 0xfe), *check(0xf, 0), *passed(),
+    # 4: 8xy7
+    (LD, 0, 5), (LD, 1, 7), (ALU, 0, 1, 7), *check(0, 2), *check(0xf, 1), *passed(),
+    # 5: 8xy1, 8xy2, 8xy3
+    (LD, 0, 0x0f), (LD, 1, 0x3c), (ALU, 0, 1, 1), *check(0, 0x3f),
+    (ALU, 0, 1, 2), *check(0, 0x3c), (ALU, 0, 1, 3), *check(0, 0), *passed(),
+    # 6: 8xx6 and 8xxE, the same register so the shift quirk doesn't matter
+    (LD, 0, 0x81), (ALU, 0, 0, 6), *check(0, 0x40), *check(0xf, 1),
+    (ALU, 0, 0, 0xe), *check(0, 0x80), *check(0xf, 0), *passed(),
+    # 7: 5xy0, 9xy0, 4xkk
+    (LD, 0, 9), (LD, 1, 9), (SEV, 0, 1), (JP, 'fail'), (ADD, 1, 1), (SNEV, 0, 1), (JP, 'fail'),
+    (SNE, 0, 8), (JP, 'fail'), *passed(),
+    # 8: 2nnn / 00EE
+    (LD, 2, 0), (CALL, 'sub'), *check(2, 0x42), *passed(),
+    # 9: Fx33 then Fx55/Fx65
+    (LDI, 'scratch'), (LD, 0, 123), (FX, 0, 0x33), (FX, 2, 0x65),
+    *check(0, 1), *check(1, 2), *check(2, 3), *passed(),
+    # 10: Fx1E
+    (LDI, 'scratch'), (LD, 0, 2), (FX, 0, 0x1e), (FX, 0, 0x65), *check(0, 3), *passed(),
+    # All passed: show the count.
+    (LD, 6, 0), (FX, 5, 0x29), (DRW, 6, 6, 5),
+    'done:', (JP, 'done'),
+    'fail:', (LD, 6, 0xe), (LD, 7, 0), (FX, 6, 0x29), (DRW, 7, 7, 5),
+    (LD, 8, 5), (FX, 5, 0x29), (DRW, 8, 7, 5),
+    'failed:', (JP, 'failed'),
+    'sub:', (LD, 2, 0x42), (RET,),
+    'scratch:', ('bytes', [0, 0, 0, 0]),
+]
+
+# Adds a bit to V5 for each quirk the interpreter has, then draws V5 in decimal at the top
+# left. Everything has to stay below 0x300 for the Bnnn check.
+quirks = [
+    (CLS,), (LD, 5, 0),
+    # 1: vf_reset_on_logic, 8xy1 clears VF
+    (LD, 0xf, 5), (LD, 0, 1), (ALU, 0, 0, 1), (SNE, 0xf, 0), (ADD, 5, 1),
+    # 2: shift_uses_vy, 8xy6 shifts Vy into Vx
+    (LD, 0, 0), (LD, 1, 4), (ALU, 0, 1, 6), (SNE, 0, 2), (ADD, 5, 2),
+    # 4: load_store_increments_i, the Fx55 after an Fx65 lands one byte further on
+    (LDI, 'scratch'), (FX, 0, 0x65), (LD, 0, 7), (FX, 0, 0x55),
+    (LDI, 'scratch1'), (FX, 0, 0x65), (SNE, 0, 7), (ADD, 5, 4),
+    # 8: jump_with_vx, B2nn adds V2 instead of V0
+    (LD, 0, 0), (LD, 2, 4), (JPV0, 'jumps'),
+    'jumps:', (JP, 'jumped'), (JP, 'jumped'), (ADD, 5, 8),
+    'jumped:',
+    # 16: clip_sprites, a sprite on the right edge doesn't wrap onto the left
+    (LD, 0, 60), (LD, 1, 0), (LDI, 'full'), (DRW, 0, 1, 1),
+    (LD, 0, 0), (LDI, 'dot'), (DRW, 0, 1, 1), (SNE, 0xf, 0), (ADD, 5, 16), (CLS,),
+    # 32: add_i_sets_vf, Fx1E past 0xFFF sets VF
+    (LD, 0xf, 5), (LDI, 0xfff), (LD, 0, 1), (FX, 0, 0x1e), (SNE, 0xf, 1), (ADD, 5, 32),
+    # Draw V5's three decimal digits.
+    (LDI, 'scratch'), (FX, 5, 0x33), (FX, 2, 0x65), (LD, 3, 0), (LD, 4, 0),
+    (FX, 0, 0x29), (DRW, 3, 4, 5), (ADD, 3, 5),
+    (FX, 1, 0x29), (DRW, 3, 4, 5), (ADD, 3, 5),
+    (FX, 2, 0x29), (DRW, 3, 4, 5),
+    'end:', (JP, 'end'),
+    'full:', ('bytes', [0xff]),
+    'dot:', ('bytes', [0x80]),
+    'scratch:', ('bytes', [0]),
+    'scratch1:', ('bytes', [0, 0]),
+]
+
+keypad = [
+    'loop:', (FX, 0, 0x0a), (CLS,), (LD, 1, 30), (LD, 2, 13), (FX, 0, 0x29), (DRW, 1, 2, 5),
+    (JP, 'loop'),
+]
+
+import sys
+rom, labels = assemble(opcode)
+open(sys.argv[1] + '/opcode_test.ch8', 'wb').write(rom)
+print('opcode', len(rom), {k: hex(v) for k, v in labels.items()})
+rom, labels = assemble(quirks)
+assert labels['jumps'] < 0x300
+open(sys.argv[1] + '/quirks_test.ch8', 'wb').write(rom)
+print('quirks', len(rom), {k: hex(v) for k, v in labels.items()})
+rom, labels = assemble(keypad)
+open(sys.argv[1] + '/keypad_test.ch8', 'wb').write(rom)
+print('keypad', len(rom))
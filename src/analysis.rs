@@ -0,0 +1,240 @@
+use crate::instructions::Instruction;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What a labelled address is the target of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Label {
+    /// The target of a 2nnn call.
+    Subroutine,
+    /// The target of a 1nnn jump, that isn't also called.
+    Code,
+}
+
+/// The result of following the control flow of a program, see `Machine::analyze`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Analysis {
+    /// The address of every instruction that can be reached from the entry point.
+    pub reachable: BTreeSet<usize>,
+    /// Jump and call targets.
+    pub labels: BTreeMap<usize, Label>,
+    /// Bnnn instructions, whose targets depend on V0 and couldn't be followed.
+    pub indirect_jumps: BTreeSet<usize>,
+}
+
+impl Analysis {
+    /// Whether `addr` is the start of an instruction the program can run. Everything else is
+    /// data, or code only reached through a Bnnn jump table.
+    pub fn is_code(&self, addr: usize) -> bool {
+        self.reachable.contains(&addr)
+    }
+
+    pub fn label(&self, addr: usize) -> Option<Label> {
+        self.labels.get(&addr).copied()
+    }
+
+    fn add_label(&mut self, addr: usize, label: Label) {
+        let existing = self.labels.entry(addr).or_insert(label);
+        if label == Label::Subroutine {
+            *existing = label;
+        }
+    }
+}
+
+/// Walks the program in `memory` from `start`, following jumps, calls and both sides of
+/// every skip. Stops down a path at the first word that doesn't decode.
+pub fn analyze(memory: &[u8], start: usize, xo_chip: bool) -> Analysis {
+    let mut analysis = Analysis::default();
+    let mut pending = vec![start];
+
+    while let Some(addr) = pending.pop() {
+        if analysis.reachable.contains(&addr) {
+            continue;
+        }
+        let Some(ins) = decode_at(memory, addr, xo_chip) else {
+            continue;
+        };
+        analysis.reachable.insert(addr);
+
+        let next = addr + ins.size();
+        match ins {
+            Instruction::Jmp(nnn) => {
+                analysis.add_label(nnn as usize, Label::Code);
+                pending.push(nnn as usize);
+            }
+            Instruction::Call(nnn) => {
+                analysis.add_label(nnn as usize, Label::Subroutine);
+                pending.push(nnn as usize);
+                pending.push(next);
+            }
+            Instruction::Ret => {}
+            Instruction::JmpV0(_) => {
+                analysis.indirect_jumps.insert(addr);
+            }
+            Instruction::SkipEq(..)
+            | Instruction::SkipNEq(..)
+            | Instruction::SkipEqV(..)
+            | Instruction::Sne(..)
+            | Instruction::SkipPressed(_)
+            | Instruction::SkipNPressed(_) => {
+                pending.push(next);
+                let skipped = decode_at(memory, next, xo_chip).map_or(2, |ins| ins.size());
+                pending.push(next + skipped);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    analysis
+}
+
+/// The platform a ROM was written for, as far as its instructions tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Chip8Variant {
+    /// Only uses the original instruction set.
+    Chip8,
+    /// Uses SUPER-CHIP instructions: high resolution, scrolling, big digits or the RPL flags.
+    SuperChip,
+    /// Uses XO-CHIP instructions: F000 long loads, bit planes or the audio pattern.
+    XoChip,
+}
+
+/// Guesses the platform `rom` targets from the most advanced instruction its reachable code
+/// uses. Bytes that are only data, as far as `analyze` can tell, don't count, so the guess
+/// can fall short for code only reached through Bnnn jump tables.
+pub fn detect_variant(rom: &[u8]) -> Chip8Variant {
+    const START: usize = 0x200;
+
+    let mut memory = vec![0; START + rom.len()];
+    memory[START..].copy_from_slice(rom);
+
+    let analysis = analyze(&memory, START, true);
+    analysis
+        .reachable
+        .iter()
+        .filter_map(|&addr| decode_at(&memory, addr, true))
+        .map(|ins| match ins {
+            Instruction::LongLoadI(_)
+            | Instruction::SelectPlanes(_)
+            | Instruction::LoadAudio
+            | Instruction::SetPitch(_) => Chip8Variant::XoChip,
+            Instruction::HighRes
+            | Instruction::LowRes
+            | Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::LoadBigSprite(_)
+            | Instruction::StoreFlags(_)
+            | Instruction::LoadFlags(_) => Chip8Variant::SuperChip,
+            _ => Chip8Variant::Chip8,
+        })
+        .max()
+        .unwrap_or(Chip8Variant::Chip8)
+}
+
+fn decode_at(memory: &[u8], addr: usize, xo_chip: bool) -> Option<Instruction> {
+    let word = |addr: usize| u16::from_be_bytes([memory[addr], memory[addr + 1]]);
+
+    if addr + 1 >= memory.len() {
+        return None;
+    }
+    if xo_chip && word(addr) == 0xf000 {
+        return (addr + 3 < memory.len()).then(|| Instruction::LongLoadI(word(addr + 2)));
+    }
+    Instruction::try_from(word(addr)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analysis::{analyze, detect_variant, Chip8Variant, Label};
+    use crate::roms::BuiltinRom;
+
+    fn memory(program: &[u8]) -> Vec<u8> {
+        let mut memory = vec![0; 4096];
+        memory[0x200..0x200 + program.len()].copy_from_slice(program);
+        memory
+    }
+
+    #[test]
+    fn test_skips_data() {
+        let memory = memory(&[
+            0x22, 0x08, // 200: CALL 0x208
+            0x12, 0x06, // 202: JP 0x206
+            0xff, 0xff, // 204: data
+            0x12, 0x06, // 206: JP 0x206
+            0x30, 0x01, // 208: SE V0, 1
+            0x00, 0xee, // 20A: RET
+            0x00, 0xee, // 20C: RET
+        ]);
+
+        let analysis = analyze(&memory, 0x200, false);
+        assert_eq!(
+            analysis.reachable.iter().copied().collect::<Vec<_>>(),
+            vec![0x200, 0x202, 0x206, 0x208, 0x20a, 0x20c]
+        );
+        assert!(!analysis.is_code(0x204));
+        assert_eq!(analysis.label(0x208), Some(Label::Subroutine));
+        assert_eq!(analysis.label(0x206), Some(Label::Code));
+        assert_eq!(analysis.label(0x200), None);
+    }
+
+    #[test]
+    fn test_indirect_jump() {
+        let memory = memory(&[
+            0xb2, 0x04, // 200: JP V0, 0x204
+            0x12, 0x02, // 202: unreachable
+        ]);
+
+        let analysis = analyze(&memory, 0x200, false);
+        assert!(analysis.indirect_jumps.contains(&0x200));
+        assert!(!analysis.is_code(0x202));
+    }
+
+    #[test]
+    fn test_skip_over_long_load() {
+        let memory = memory(&[
+            0x30, 0x01, // 200: SE V0, 1
+            0xf0, 0x00, 0x12, 0x06, // 202: LD I, long 0x1206
+            0x12, 0x06, // 206: JP 0x206
+        ]);
+
+        let analysis = analyze(&memory, 0x200, true);
+        assert!(analysis.is_code(0x202));
+        assert!(!analysis.is_code(0x204));
+        assert!(analysis.is_code(0x206));
+    }
+
+    #[test]
+    fn test_detect_variant() {
+        assert_eq!(
+            detect_variant(BuiltinRom::IbmLogo.bytes()),
+            Chip8Variant::Chip8
+        );
+
+        // 00FF - HIGH
+        // 1202 - JP 0x202
+        assert_eq!(
+            detect_variant(&[0x00, 0xff, 0x12, 0x02]),
+            Chip8Variant::SuperChip
+        );
+
+        // F000 0300 - LD I, long 0x300
+        // 00FF      - HIGH
+        // F201      - PLANE 2
+        // 1208      - JP 0x208
+        assert_eq!(
+            detect_variant(&[0xf0, 0x00, 0x03, 0x00, 0x00, 0xff, 0xf2, 0x01, 0x12, 0x08]),
+            Chip8Variant::XoChip
+        );
+    }
+
+    #[test]
+    fn test_detect_variant_ignores_data() {
+        // 1204 - JP 0x204
+        // 00FF - data that happens to read as HIGH
+        // 1204 - JP 0x204
+        assert_eq!(
+            detect_variant(&[0x12, 0x04, 0x00, 0xff, 0x12, 0x04]),
+            Chip8Variant::Chip8
+        );
+    }
+}
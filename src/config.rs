@@ -0,0 +1,286 @@
+use crate::keymap::KeyMap;
+use chip8::cost::CostModel;
+use chip8::palette::{Color, Palette};
+use chip8::profiles::{parse_hash, Profiles};
+use chip8::quirks::Quirks;
+use piston_window::Key;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Where the config is read from when `--config` isn't given.
+pub const DEFAULT_PATH: &str = "chip8.toml";
+
+/// Settings read from a TOML file, every field is optional:
+///
+/// ```toml
+/// clock_hz = 700
+/// scale = 12
+/// theme = "amber"
+/// pause_in_background = false
+///
+/// # Make stores into the fonts and these [start, end) ranges errors, see `Machine::protect`.
+/// protect_font = true
+/// write_protect = [[0x200, 0x300]]
+/// foreground = [1.0, 0.5, 0.0, 1.0]
+///
+/// [quirks]
+/// shift_uses_vy = true
+///
+/// # Cycles per instruction, clock_hz counts these. Draws cost draw + draw_per_row * n, with
+/// # n = 16 for Dxy0 in high resolution and 0 in low resolution.
+/// [cost]
+/// draw = 2
+/// draw_per_row = 1
+///
+/// # Bound on top of the default layout, an empty list unbinds a key.
+/// [keys]
+/// Up = [2]
+/// Space = [5]
+///
+/// # Like [keys], but the CHIP-8 keys are only tapped for a frame per press.
+/// [pulse]
+/// Return = [5, 6]
+///
+/// # Quirks for particular ROMs, by the SHA-256 `sha256sum` prints. These replace [quirks]
+/// for that ROM and take precedence over the built-in profiles.
+/// [profiles.8bf3b46d8a64c2074e7538200f684a2eaced258404d3c7d3bd7a917c3d0143e5]
+/// display_wait = false
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Cycles run per second, one per instruction unless `cost` makes draws take longer.
+    pub clock_hz: u32,
+    /// Host pixels per CHIP-8 pixel, for the window, screenshots and recordings.
+    pub scale: u32,
+    pub xo_chip: bool,
+    /// Report jumps to odd addresses as errors and skip them, see
+    /// `Machine::set_strict_alignment`.
+    pub strict_alignment: bool,
+    /// Wrap memory accesses past the end of RAM around to address 0 instead of reporting
+    /// them, see `Machine::set_wrap_memory`.
+    pub wrap_memory: bool,
+    /// Pause and go quiet while the window doesn't have focus.
+    pub pause_in_background: bool,
+    /// Start with the clickable hex keypad beside the game, F4 toggles it.
+    pub show_keypad: bool,
+    /// Report stores into the fonts as errors and skip them.
+    pub protect_font: bool,
+    /// Like `protect_font`, for each `[start, end)` range.
+    pub write_protect: Vec<[usize; 2]>,
+    /// One of the built-in palettes, see `Palette::named`.
+    pub theme: String,
+    /// Overrides the theme's background.
+    pub background: Option<Color>,
+    /// Overrides the theme's foreground.
+    pub foreground: Option<Color>,
+    pub quirks: Quirks,
+    pub cost: CostModel,
+    pub keys: HashMap<Key, Vec<u8>>,
+    pub pulse: HashMap<Key, Vec<u8>>,
+    pub profiles: HashMap<String, Quirks>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            clock_hz: 540,
+            scale: 20,
+            xo_chip: false,
+            strict_alignment: false,
+            wrap_memory: false,
+            pause_in_background: true,
+            show_keypad: false,
+            protect_font: false,
+            write_protect: Vec::new(),
+            theme: "classic".to_string(),
+            background: None,
+            foreground: None,
+            quirks: Quirks::default(),
+            cost: CostModel::default(),
+            keys: HashMap::new(),
+            pulse: HashMap::new(),
+            profiles: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_toml(s: &str) -> Result<Self, String> {
+        let config: Config = toml::from_str(s).map_err(|e| e.to_string())?;
+
+        if Palette::named(&config.theme).is_none() {
+            return Err(format!(
+                "unknown theme {}, expected one of classic, green, amber or mono",
+                config.theme
+            ));
+        }
+        if config.clock_hz == 0 || config.scale == 0 {
+            return Err("clock_hz and scale must be above 0".to_string());
+        }
+        if config.cost.draw == 0 {
+            return Err("cost.draw must be above 0".to_string());
+        }
+        if let Some([start, end]) = config
+            .write_protect
+            .iter()
+            .find(|[start, end]| start >= end)
+        {
+            return Err(format!(
+                "write_protect range [{start:#x}, {end:#x}] is empty, the start has to come first"
+            ));
+        }
+        let mut bound = config.keys.values().chain(config.pulse.values()).flatten();
+        if let Some(k) = bound.find(|&&k| k > 0xf) {
+            return Err(format!("{k:#x} is not a CHIP-8 key"));
+        }
+
+        if let Some(hash) = config.profiles.keys().find(|h| parse_hash(h).is_none()) {
+            return Err(format!("{hash} is not a SHA-256 hash"));
+        }
+
+        Ok(config)
+    }
+
+    /// Reads the config at `path`. A missing file gives the defaults when `required` is false.
+    pub fn load(path: &Path, required: bool) -> Result<Self, String> {
+        match read_to_string(path) {
+            Ok(s) => Config::from_toml(&s),
+            Err(e) if e.kind() == ErrorKind::NotFound && !required => Ok(Config::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn palette(&self) -> Palette {
+        let mut palette = Palette::named(&self.theme).unwrap_or_default();
+        if let Some(background) = self.background {
+            palette.background = background;
+        }
+        if let Some(foreground) = self.foreground {
+            palette.foreground = foreground;
+        }
+        palette
+    }
+
+    /// The built-in profiles extended with the ones in the config.
+    pub fn profiles(&self) -> Profiles {
+        let mut profiles = Profiles::default();
+        for (hash, &quirks) in &self.profiles {
+            if let Some(hash) = parse_hash(hash) {
+                profiles.insert(hash, quirks);
+            }
+        }
+        profiles
+    }
+
+    pub fn keymap(&self) -> KeyMap {
+        let mut map = KeyMap::default();
+        for (&key, chip8_keys) in &self.keys {
+            map.bind(key, chip8_keys.clone());
+        }
+        for (&key, chip8_keys) in &self.pulse {
+            map.bind_pulse(key, chip8_keys.clone());
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use chip8::palette::Palette;
+    use chip8::quirks::Quirks;
+    use chip8::roms::BuiltinRom;
+    use piston_window::Key;
+    use std::path::Path;
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+        assert_eq!(
+            Config::load(Path::new("does-not-exist.toml"), false).unwrap(),
+            Config::default()
+        );
+        assert!(Config::load(Path::new("does-not-exist.toml"), true).is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = Config::from_toml(
+            r#"
+            clock_hz = 700
+            theme = "amber"
+            background = [0.0, 0.0, 0.0, 1.0]
+            write_protect = [[0x200, 0x300]]
+
+            [quirks]
+            shift_uses_vy = true
+
+            [cost]
+            draw_per_row = 2
+
+            [keys]
+            Up = [2]
+            Q = []
+
+            [pulse]
+            Return = [5, 6]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.clock_hz, 700);
+        assert_eq!(config.scale, 20);
+        assert!(config.quirks.shift_uses_vy);
+        assert!(!config.quirks.jump_with_vx);
+        assert_eq!(config.cost.draw, 1);
+        assert_eq!(config.cost.draw_per_row, 2);
+        assert_eq!(config.write_protect, [[0x200, 0x300]]);
+
+        let palette = config.palette();
+        assert_eq!(palette.background, [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(palette.foreground, Palette::amber().foreground);
+
+        let keymap = config.keymap();
+        assert_eq!(keymap.get(Key::Up), &[0x2]);
+        assert_eq!(keymap.get(Key::Q), &[] as &[u8]);
+        assert_eq!(keymap.get(Key::W), &[0x5]);
+        assert_eq!(keymap.get(Key::Return), &[0x5, 0x6]);
+        assert!(keymap.is_pulse(Key::Return));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let config = Config::from_toml(
+            r#"
+            [profiles.8bf3b46d8a64c2074e7538200f684a2eaced258404d3c7d3bd7a917c3d0143e5]
+            jump_with_vx = true
+            "#,
+        )
+        .unwrap();
+
+        let quirks = Quirks {
+            jump_with_vx: true,
+            ..Quirks::default()
+        };
+        assert_eq!(
+            config.profiles().get(BuiltinRom::IbmLogo.bytes()),
+            Some(quirks)
+        );
+        assert!(Config::from_toml("[profiles.abc]\njump_with_vx = true").is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Config::from_toml("clock = 700").is_err());
+        assert!(Config::from_toml("theme = \"pink\"").is_err());
+        assert!(Config::from_toml("[keys]\nQ = [16]").is_err());
+        assert!(Config::from_toml("[pulse]\nQ = [16]").is_err());
+        assert!(Config::from_toml("[quirks]\nshift = true").is_err());
+        assert!(Config::from_toml("[cost]\ndraw = 0").is_err());
+        assert!(Config::from_toml("write_protect = [[0x300, 0x200]]").is_err());
+    }
+}
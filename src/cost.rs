@@ -0,0 +1,70 @@
+use crate::instructions::Instruction;
+use serde::Deserialize;
+
+/// How many clock cycles each instruction takes, for hosts that pace the CPU by cycles rather
+/// than by instructions.
+///
+/// Everything costs one cycle except Dxyn, which costs `draw` plus `draw_per_row` for each
+/// row of the sprite. Dxy0 counts 16 rows in high resolution and none in low resolution,
+/// where it draws nothing. The default makes draws cost one cycle too, so a cycle is an
+/// instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CostModel {
+    pub draw: u32,
+    pub draw_per_row: u32,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            draw: 1,
+            draw_per_row: 0,
+        }
+    }
+}
+
+impl CostModel {
+    /// The cycles `ins` takes, `hires` being the resolution it ran in.
+    pub fn cost(&self, ins: &Instruction, hires: bool) -> u32 {
+        match *ins {
+            // Dxy0 is SUPER-CHIP's 16x16 sprite.
+            Instruction::Drw(_, _, 0) if hires => self.draw + self.draw_per_row * 16,
+            Instruction::Drw(_, _, n) => self.draw + self.draw_per_row * n as u32,
+            _ => 1,
+        }
+    }
+}
+
+/// The cost of `ins` under the default model.
+pub fn cost(ins: &Instruction, hires: bool) -> u32 {
+    CostModel::default().cost(ins, hires)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cost::{cost, CostModel};
+    use crate::instructions::Instruction;
+
+    #[test]
+    fn test_default_costs_one_cycle() {
+        assert_eq!(cost(&Instruction::Cls, false), 1);
+        assert_eq!(cost(&Instruction::Drw(0, 1, 15), false), 1);
+        assert_eq!(cost(&Instruction::LongLoadI(0x1234), true), 1);
+    }
+
+    #[test]
+    fn test_draw_cost_scales_with_rows() {
+        let model = CostModel {
+            draw: 4,
+            draw_per_row: 2,
+        };
+        assert_eq!(model.cost(&Instruction::Add(0, 1), false), 1);
+        assert_eq!(model.cost(&Instruction::Drw(0, 1, 5), false), 14);
+        assert_eq!(model.cost(&Instruction::Drw(0, 1, 5), true), 14);
+        // Dxy0 draws 16 rows in high resolution and nothing in low resolution.
+        assert_eq!(model.cost(&Instruction::Drw(0, 1, 0), true), 36);
+        assert_eq!(model.cost(&Instruction::Drw(0, 1, 0), false), 4);
+        assert_eq!(cost(&Instruction::Drw(0, 1, 0), true), 1);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::machine::MachineState;
+use std::fmt::{Display, Formatter};
+
+/// One way two `MachineState`s differ, see `MachineState::diff`. Values are `(old, new)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateDiff {
+    /// Vx.
+    Register(usize, u8, u8),
+    I(u16, u16),
+    Delay(u8, u8),
+    Sound(u8, u8),
+    Pc(usize, usize),
+    Sp(usize, usize),
+    /// A stack slot, by index.
+    Stack(usize, u16, u16),
+    SelectedPlanes(u8, u8),
+    /// The RAM size, when one state is in XO-CHIP mode and the other isn't. Only the
+    /// addresses both have are compared.
+    MemorySize(usize, usize),
+    Memory(usize, u8, u8),
+    /// The resolution. Pixels aren't compared when it differs.
+    ScreenSize((usize, usize), (usize, usize)),
+    /// The pixel at `(x, y)`, as one bit per plane.
+    Pixel(usize, usize, u8, u8),
+}
+
+impl Display for StateDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            StateDiff::Register(x, old, new) => write!(f, "V{x:X}: {old:#04x} -> {new:#04x}"),
+            StateDiff::I(old, new) => write!(f, "I: {old:#05x} -> {new:#05x}"),
+            StateDiff::Delay(old, new) => write!(f, "DT: {old} -> {new}"),
+            StateDiff::Sound(old, new) => write!(f, "ST: {old} -> {new}"),
+            StateDiff::Pc(old, new) => write!(f, "PC: {old:#05x} -> {new:#05x}"),
+            StateDiff::Sp(old, new) => write!(f, "SP: {old} -> {new}"),
+            StateDiff::Stack(i, old, new) => write!(f, "stack[{i}]: {old:#05x} -> {new:#05x}"),
+            StateDiff::SelectedPlanes(old, new) => write!(f, "planes: {old} -> {new}"),
+            StateDiff::MemorySize(old, new) => write!(f, "RAM size: {old} -> {new}"),
+            StateDiff::Memory(addr, old, new) => {
+                write!(f, "[{addr:#05x}]: {old:#04x} -> {new:#04x}")
+            }
+            StateDiff::ScreenSize((w1, h1), (w2, h2)) => {
+                write!(f, "screen: {w1}x{h1} -> {w2}x{h2}")
+            }
+            StateDiff::Pixel(x, y, old, new) => write!(f, "pixel ({x}, {y}): {old} -> {new}"),
+        }
+    }
+}
+
+impl MachineState {
+    /// Everything that differs going from `self` to `other`: the CPU registers first, then
+    /// memory by address, then the screen row by row.
+    pub fn diff(&self, other: &MachineState) -> Vec<StateDiff> {
+        let mut diffs = Vec::new();
+
+        for (x, (&old, &new)) in self.registers.iter().zip(&other.registers).enumerate() {
+            if old != new {
+                diffs.push(StateDiff::Register(x, old, new));
+            }
+        }
+        if self.register_i != other.register_i {
+            diffs.push(StateDiff::I(self.register_i, other.register_i));
+        }
+        if self.register_delay != other.register_delay {
+            diffs.push(StateDiff::Delay(self.register_delay, other.register_delay));
+        }
+        if self.register_sound != other.register_sound {
+            diffs.push(StateDiff::Sound(self.register_sound, other.register_sound));
+        }
+        if self.pc != other.pc {
+            diffs.push(StateDiff::Pc(self.pc, other.pc));
+        }
+        if self.sp != other.sp {
+            diffs.push(StateDiff::Sp(self.sp, other.sp));
+        }
+        for (i, (&old, &new)) in self.stack.iter().zip(&other.stack).enumerate() {
+            if old != new {
+                diffs.push(StateDiff::Stack(i, old, new));
+            }
+        }
+        if self.selected_planes != other.selected_planes {
+            diffs.push(StateDiff::SelectedPlanes(
+                self.selected_planes,
+                other.selected_planes,
+            ));
+        }
+
+        if self.ram.len() != other.ram.len() {
+            diffs.push(StateDiff::MemorySize(self.ram.len(), other.ram.len()));
+        }
+        for (addr, (&old, &new)) in self.ram.iter().zip(&other.ram).enumerate() {
+            if old != new {
+                diffs.push(StateDiff::Memory(addr, old, new));
+            }
+        }
+
+        if self.screen_size != other.screen_size {
+            diffs.push(StateDiff::ScreenSize(self.screen_size, other.screen_size));
+        } else {
+            let width = self.screen_size.0;
+            for (i, (&old, &new)) in self.screen.iter().zip(&other.screen).enumerate() {
+                if old != new {
+                    diffs.push(StateDiff::Pixel(i % width, i / width, old, new));
+                }
+            }
+        }
+
+        diffs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::StateDiff;
+    use crate::keyboard::Keyboard;
+    use crate::machine::Machine;
+    use crate::quirks::Quirks;
+    use crate::screen::Screen;
+
+    #[test]
+    fn test_diff() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 6307 - LD V3, 7
+        // A300 - LD I, 0x300
+        // F355 - LD [I], V3
+        machine.load(&[0x63, 0x07, 0xa3, 0x00, 0xf3, 0x55]).unwrap();
+        machine.run_headless(2, &keyboard, &mut screen).unwrap();
+        let before = machine.save_state(&screen);
+        assert!(before.diff(&before).is_empty());
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        let after = machine.save_state(&screen);
+
+        assert_eq!(
+            before.diff(&after),
+            [StateDiff::Pc(0x204, 0x206), StateDiff::Memory(0x303, 0, 7)]
+        );
+
+        machine.poke(0x301, &[9]).unwrap();
+        let mut changed = machine.save_state(&screen);
+        changed.registers[3] = 8;
+        changed.screen[64 + 2] = 1;
+
+        let diffs = after.diff(&changed);
+        assert_eq!(
+            diffs,
+            [
+                StateDiff::Register(3, 7, 8),
+                StateDiff::Memory(0x301, 0, 9),
+                StateDiff::Pixel(2, 1, 0, 1),
+            ]
+        );
+        let lines: Vec<_> = diffs.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "V3: 0x07 -> 0x08",
+                "[0x301]: 0x00 -> 0x09",
+                "pixel (2, 1): 0 -> 1"
+            ]
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use chip8::keyboard::Keyboard;
+use gilrs::{Button, EventType, GamepadId, Gilrs};
+use std::collections::HashMap;
+
+/// Maps gamepad buttons to the CHIP-8 keys they press, the gamepad counterpart of `KeyMap`.
+pub struct ButtonMap {
+    buttons: HashMap<Button, Vec<u8>>,
+}
+
+impl ButtonMap {
+    /// An empty map, with nothing bound.
+    pub fn new() -> Self {
+        ButtonMap {
+            buttons: HashMap::new(),
+        }
+    }
+
+    /// Binds `button` to `chip8_keys`, replacing whatever it was bound to.
+    pub fn bind(&mut self, button: Button, chip8_keys: Vec<u8>) {
+        self.buttons.insert(button, chip8_keys);
+    }
+
+    /// The CHIP-8 keys pressed by `button`, empty when it isn't bound.
+    pub fn get(&self, button: Button) -> &[u8] {
+        self.buttons.get(&button).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+impl Default for ButtonMap {
+    /// The d-pad drives 2/4/6/8, which most games use for directions, and the face buttons
+    /// press 5, the usual action key, plus a few others.
+    fn default() -> Self {
+        let mut map = ButtonMap::new();
+        map.bind(Button::DPadUp, vec![0x2]);
+        map.bind(Button::DPadLeft, vec![0x4]);
+        map.bind(Button::DPadRight, vec![0x6]);
+        map.bind(Button::DPadDown, vec![0x8]);
+        map.bind(Button::South, vec![0x5]);
+        map.bind(Button::East, vec![0x0]);
+        map.bind(Button::West, vec![0xa]);
+        map.bind(Button::North, vec![0xb]);
+        map.bind(Button::Select, vec![0xe]);
+        map.bind(Button::Start, vec![0xf]);
+        map
+    }
+}
+
+/// Feeds gamepad buttons into the `Keyboard`, from any number of pads plugged in or out while
+/// running.
+pub struct GamepadInput {
+    gilrs: Gilrs,
+    map: ButtonMap,
+    held: HashMap<(GamepadId, Button), Vec<u8>>,
+}
+
+impl GamepadInput {
+    pub fn new(map: ButtonMap) -> Result<Self, String> {
+        let gilrs = Gilrs::new().map_err(|e| e.to_string())?;
+
+        Ok(GamepadInput {
+            gilrs,
+            map,
+            held: HashMap::new(),
+        })
+    }
+
+    /// Applies every gamepad event since the last call, meant to be called once per loop.
+    pub fn poll(&mut self, keyboard: &mut Keyboard) {
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    let keys = self.map.get(button).to_vec();
+                    for &k in &keys {
+                        keyboard.press(k as usize);
+                    }
+                    self.held.insert((event.id, button), keys);
+                }
+                EventType::ButtonReleased(button, _) => {
+                    for k in self.held.remove(&(event.id, button)).unwrap_or_default() {
+                        keyboard.release(k as usize);
+                    }
+                }
+                EventType::Connected => {
+                    println!("gamepad connected: {}", self.gilrs.gamepad(event.id).name());
+                }
+                // Buttons held on an unplugged pad would otherwise stay down forever.
+                EventType::Disconnected => {
+                    self.held.retain(|(id, _), keys| {
+                        if *id == event.id {
+                            for &k in keys.iter() {
+                                keyboard.release(k as usize);
+                            }
+                        }
+                        *id != event.id
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gamepad::ButtonMap;
+    use gilrs::Button;
+
+    #[test]
+    fn test_default_map() {
+        let map = ButtonMap::default();
+        assert_eq!(map.get(Button::DPadUp), &[0x2]);
+        assert_eq!(map.get(Button::South), &[0x5]);
+        assert_eq!(map.get(Button::LeftTrigger), &[] as &[u8]);
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut map = ButtonMap::new();
+        map.bind(Button::South, vec![0x1, 0x2]);
+        assert_eq!(map.get(Button::South), &[0x1, 0x2]);
+    }
+}
@@ -0,0 +1,93 @@
+use chip8::machine::Machine;
+
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+
+/// The registers, I, PC, SP and timers as a few lines of text.
+pub fn lines(machine: &Machine) -> Vec<String> {
+    let mut lines: Vec<String> = machine
+        .registers()
+        .chunks(4)
+        .enumerate()
+        .map(|(row, registers)| {
+            let cells: Vec<_> = registers
+                .iter()
+                .enumerate()
+                .map(|(i, v)| format!("V{:X} {v:02X}", row * 4 + i))
+                .collect();
+            cells.join(" ")
+        })
+        .collect();
+
+    lines.push(format!("I {:04X} PC {:04X}", machine.i(), machine.pc()));
+    lines.push(format!(
+        "SP {:X} DT {:02X} ST {:02X}",
+        machine.sp(),
+        machine.delay_timer(),
+        machine.sound_timer()
+    ));
+    lines
+}
+
+/// A tiny bitmap font covering what `lines` prints, each row's low three bits are pixels,
+/// left to right. Anything else is blank.
+pub fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hud::{glyph, lines};
+    use chip8::machine::Machine;
+    use chip8::quirks::Quirks;
+
+    #[test]
+    fn test_lines() {
+        let mut machine = Machine::new(Quirks::default());
+        machine.poke(0x200, &[0x6a, 0x42]).unwrap();
+        machine
+            .run_headless(1, &Default::default(), &mut Default::default())
+            .unwrap();
+
+        let lines = lines(&machine);
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "V0 00 V1 00 V2 00 V3 00");
+        assert_eq!(lines[2], "V8 00 V9 00 VA 42 VB 00");
+        assert_eq!(lines[4], "I 0000 PC 0202");
+        assert_eq!(lines[5], "SP 0 DT 00 ST 00");
+    }
+
+    #[test]
+    fn test_every_character_has_a_glyph() {
+        let machine = Machine::new(Quirks::default());
+        for c in lines(&machine).concat().chars().filter(|&c| c != ' ') {
+            assert_ne!(glyph(c), [0; 5], "{c}");
+        }
+        for c in "0123456789ABCDEF".chars() {
+            assert_ne!(glyph(c), [0; 5], "{c}");
+        }
+    }
+}
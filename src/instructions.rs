@@ -1,17 +1,75 @@
+use std::fmt::{Display, Formatter};
+
 type U4 = u8;
 
 type U12 = u16;
 
+/// Returned when a 16-bit word doesn't correspond to any known instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    /// The raw word that failed to decode.
+    pub opcode: u16,
+    /// The address the word was fetched from, if known.
+    pub pc: Option<usize>,
+}
+
+impl DecodeError {
+    pub fn at(self, pc: usize) -> Self {
+        DecodeError {
+            pc: Some(pc),
+            ..self
+        }
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.pc {
+            Some(pc) => write!(f, "unknown opcode {:#06x} at {:#05x}", self.opcode, pc),
+            None => write!(f, "unknown opcode {:#06x}", self.opcode),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
     /// 0nnn - SYS addr
     ///
     /// Jump to a machine code routine at nnn.
+    ///
+    /// There's no machine code to run here, so it's ignored like on most interpreters.
     Sys(U12),
 
     /// 00E0 - CLSP
     /// Clear the display.
     Cls,
 
+    /// 00FE - LOW
+    /// Disable high resolution mode (SUPER-CHIP).
+    ///
+    /// Switches the display back to 64x32 and clears it.
+    LowRes,
+
+    /// 00FF - HIGH
+    /// Enable high resolution mode (SUPER-CHIP).
+    ///
+    /// Switches the display to 128x64 and clears it.
+    HighRes,
+
+    /// 00Cn - SCD n
+    /// Scroll the display down n pixels (SUPER-CHIP).
+    ScrollDown(U4),
+
+    /// 00FB - SCR
+    /// Scroll the display right 4 pixels (SUPER-CHIP).
+    ScrollRight,
+
+    /// 00FC - SCL
+    /// Scroll the display left 4 pixels (SUPER-CHIP).
+    ScrollLeft,
+
     /// 00EE - RET
     /// Return from a subroutine.
     ///
@@ -209,6 +267,12 @@ pub enum Instruction {
     /// See section 2.4, Display, for more information on the Chip-8 hexadecimal font.
     LoadSprite(U4),
 
+    /// Fx30 - LD HF, Vx
+    /// Set I = location of the large sprite for digit Vx (SUPER-CHIP).
+    ///
+    /// The large digits are 8x10, ten bytes each.
+    LoadBigSprite(U4),
+
     /// Fx33 - LD B, Vx
     /// Store BCD representation of Vx in memory locations I, I+1, and I+2.
     ///
@@ -227,14 +291,196 @@ pub enum Instruction {
     ///
     /// The interpreter reads values from memory starting at location I into registers V0 through Vx.
     SetAllI(U4),
+
+    /// Fx75 - LD R, Vx
+    /// Store registers V0 through Vx in the RPL user flags (SUPER-CHIP).
+    ///
+    /// There are eight flags, x above 7 is treated as 7.
+    StoreFlags(U4),
+
+    /// Fx85 - LD Vx, R
+    /// Read registers V0 through Vx from the RPL user flags (SUPER-CHIP).
+    LoadFlags(U4),
+
+    /// F002 - AUDIO (XO-CHIP)
+    /// Load the 16 bytes at I into the audio pattern buffer.
+    ///
+    /// The pattern is 128 one-bit samples, played in a loop while the sound timer is running.
+    LoadAudio,
+
+    /// Fx3A - PITCH Vx (XO-CHIP)
+    /// Set the pitch register = Vx.
+    ///
+    /// The pattern plays at 4000 * 2^((Vx - 64) / 48) samples per second.
+    SetPitch(U4),
+
+    /// Fn01 - PLANE n (XO-CHIP)
+    /// Select the bit planes to draw to and clear.
+    ///
+    /// n is a mask, one bit per plane. 1 selects only the first plane, which is the default.
+    SelectPlanes(U4),
+
+    /// F000 nnnn - LD I, long nnnn (XO-CHIP)
+    /// Set I = nnnn.
+    ///
+    /// The only four byte instruction, the 16-bit address is the word following F000.
+    LongLoadI(u16),
+}
+
+impl Instruction {
+    /// Decodes the word `ins`, using `next` as the operand of the four byte F000 instruction.
+    ///
+    /// Every other instruction ignores `next`, `TryFrom<u16>` is enough for those.
+    pub fn decode(ins: u16, next: u16) -> Result<Self, DecodeError> {
+        match ins {
+            0xf000 => Ok(Instruction::LongLoadI(next)),
+            _ => Instruction::try_from(ins),
+        }
+    }
+
+    /// Decodes the word `ins`, for callers that know it's valid, like hand-written programs.
+    ///
+    /// # Panics
+    ///
+    /// If `ins` isn't a known instruction. Use `TryFrom<u16>` to handle that instead.
+    pub fn decode_unchecked(ins: u16) -> Self {
+        match Instruction::try_from(ins) {
+            Ok(ins) => ins,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Encodes the instruction back into its opcode, the inverse of `TryFrom<u16>`.
+    ///
+    /// Operands wider than their field are truncated to fit. `LongLoadI` encodes to F000, its
+    /// address is the word that follows, the way `decode` reads it.
+    pub fn encode(&self) -> u16 {
+        match *self {
+            Instruction::Sys(nnn) => op_nnn(0x0, nnn),
+            Instruction::Cls => 0x00e0,
+            Instruction::LowRes => 0x00fe,
+            Instruction::HighRes => 0x00ff,
+            Instruction::ScrollDown(n) => op_xyn(0x0, 0x0, 0xc, n),
+            Instruction::ScrollRight => 0x00fb,
+            Instruction::ScrollLeft => 0x00fc,
+            Instruction::Ret => 0x00ee,
+            Instruction::Jmp(nnn) => op_nnn(0x1, nnn),
+            Instruction::Call(nnn) => op_nnn(0x2, nnn),
+            Instruction::SkipEq(x, kk) => op_xkk(0x3, x, kk),
+            Instruction::SkipNEq(x, kk) => op_xkk(0x4, x, kk),
+            Instruction::SkipEqV(x, y) => op_xyn(0x5, x, y, 0x0),
+            Instruction::Set(x, kk) => op_xkk(0x6, x, kk),
+            Instruction::Add(x, kk) => op_xkk(0x7, x, kk),
+            Instruction::Load(x, y) => op_xyn(0x8, x, y, 0x0),
+            Instruction::Or(x, y) => op_xyn(0x8, x, y, 0x1),
+            Instruction::And(x, y) => op_xyn(0x8, x, y, 0x2),
+            Instruction::Xor(x, y) => op_xyn(0x8, x, y, 0x3),
+            Instruction::AddCarry(x, y) => op_xyn(0x8, x, y, 0x4),
+            Instruction::SubCarry(x, y) => op_xyn(0x8, x, y, 0x5),
+            Instruction::Shr(x, y) => op_xyn(0x8, x, y, 0x6),
+            Instruction::SubN(x, y) => op_xyn(0x8, x, y, 0x7),
+            Instruction::Shl(x, y) => op_xyn(0x8, x, y, 0xe),
+            Instruction::Sne(x, y) => op_xyn(0x9, x, y, 0x0),
+            Instruction::LoadI(nnn) => op_nnn(0xa, nnn),
+            Instruction::JmpV0(nnn) => op_nnn(0xb, nnn),
+            Instruction::Rnd(x, kk) => op_xkk(0xc, x, kk),
+            Instruction::Drw(x, y, n) => op_xyn(0xd, x, y, n),
+            Instruction::SkipPressed(x) => op_xkk(0xe, x, 0x9e),
+            Instruction::SkipNPressed(x) => op_xkk(0xe, x, 0xa1),
+            Instruction::LoadDT(x) => op_xkk(0xf, x, 0x07),
+            Instruction::LoadKeyPress(x) => op_xkk(0xf, x, 0x0a),
+            Instruction::SetDT(x) => op_xkk(0xf, x, 0x15),
+            Instruction::SetST(x) => op_xkk(0xf, x, 0x18),
+            Instruction::AddI(x) => op_xkk(0xf, x, 0x1e),
+            Instruction::LoadSprite(x) => op_xkk(0xf, x, 0x29),
+            Instruction::LoadBigSprite(x) => op_xkk(0xf, x, 0x30),
+            Instruction::LoadBCD(x) => op_xkk(0xf, x, 0x33),
+            Instruction::LoadAllI(x) => op_xkk(0xf, x, 0x55),
+            Instruction::SetAllI(x) => op_xkk(0xf, x, 0x65),
+            Instruction::StoreFlags(x) => op_xkk(0xf, x, 0x75),
+            Instruction::LoadFlags(x) => op_xkk(0xf, x, 0x85),
+            Instruction::LoadAudio => 0xf002,
+            Instruction::SetPitch(x) => op_xkk(0xf, x, 0x3a),
+            Instruction::SelectPlanes(n) => op_xkk(0xf, n, 0x01),
+            Instruction::LongLoadI(_) => 0xf000,
+        }
+    }
+
+    /// How many bytes the instruction takes up in memory.
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LongLoadI(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// The opcode pattern every instruction of this kind matches, like `8xy4` or `Fx1E`.
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            Instruction::Sys(_) => "0nnn",
+            Instruction::Cls => "00E0",
+            Instruction::ScrollDown(_) => "00Cn",
+            Instruction::ScrollRight => "00FB",
+            Instruction::ScrollLeft => "00FC",
+            Instruction::LowRes => "00FE",
+            Instruction::HighRes => "00FF",
+            Instruction::Ret => "00EE",
+            Instruction::Jmp(_) => "1nnn",
+            Instruction::Call(_) => "2nnn",
+            Instruction::SkipEq(_, _) => "3xkk",
+            Instruction::SkipNEq(_, _) => "4xkk",
+            Instruction::SkipEqV(_, _) => "5xy0",
+            Instruction::Set(_, _) => "6xkk",
+            Instruction::Add(_, _) => "7xkk",
+            Instruction::Load(_, _) => "8xy0",
+            Instruction::Or(_, _) => "8xy1",
+            Instruction::And(_, _) => "8xy2",
+            Instruction::Xor(_, _) => "8xy3",
+            Instruction::AddCarry(_, _) => "8xy4",
+            Instruction::SubCarry(_, _) => "8xy5",
+            Instruction::Shr(_, _) => "8xy6",
+            Instruction::SubN(_, _) => "8xy7",
+            Instruction::Shl(_, _) => "8xyE",
+            Instruction::Sne(_, _) => "9xy0",
+            Instruction::LoadI(_) => "Annn",
+            Instruction::JmpV0(_) => "Bnnn",
+            Instruction::Rnd(_, _) => "Cxkk",
+            Instruction::Drw(_, _, _) => "Dxyn",
+            Instruction::SkipPressed(_) => "Ex9E",
+            Instruction::SkipNPressed(_) => "ExA1",
+            Instruction::LoadDT(_) => "Fx07",
+            Instruction::LoadKeyPress(_) => "Fx0A",
+            Instruction::SetDT(_) => "Fx15",
+            Instruction::SetST(_) => "Fx18",
+            Instruction::AddI(_) => "Fx1E",
+            Instruction::LoadSprite(_) => "Fx29",
+            Instruction::LoadBigSprite(_) => "Fx30",
+            Instruction::LoadBCD(_) => "Fx33",
+            Instruction::LoadAllI(_) => "Fx55",
+            Instruction::SetAllI(_) => "Fx65",
+            Instruction::StoreFlags(_) => "Fx75",
+            Instruction::LoadFlags(_) => "Fx85",
+            Instruction::SelectPlanes(_) => "Fn01",
+            Instruction::LoadAudio => "F002",
+            Instruction::SetPitch(_) => "Fx3A",
+            Instruction::LongLoadI(_) => "F000",
+        }
+    }
 }
 
-impl From<u16> for Instruction {
-    fn from(ins: u16) -> Self {
+impl TryFrom<u16> for Instruction {
+    type Error = DecodeError;
+
+    fn try_from(ins: u16) -> Result<Self, Self::Error> {
         let t = u16_to_nibbles(ins);
-        match t {
+        let ins = match t {
             (0x0, 0x0, 0xe, 0x0) => Instruction::Cls,
             (0x0, 0x0, 0xe, 0xe) => Instruction::Ret,
+            (0x0, 0x0, 0xc, n) => Instruction::ScrollDown(n),
+            (0x0, 0x0, 0xf, 0xb) => Instruction::ScrollRight,
+            (0x0, 0x0, 0xf, 0xc) => Instruction::ScrollLeft,
+            (0x0, 0x0, 0xf, 0xe) => Instruction::LowRes,
+            (0x0, 0x0, 0xf, 0xf) => Instruction::HighRes,
             (0x3, x, k1, k2) => Instruction::SkipEq(x, kk(k1, k2)),
             (0x4, x, k1, k2) => Instruction::SkipNEq(x, kk(k1, k2)),
             (0x5, x, y, 0x0) => Instruction::SkipEqV(x, y),
@@ -262,13 +508,80 @@ impl From<u16> for Instruction {
             (0xf, x, 0x1, 0x8) => Instruction::SetST(x),
             (0xf, x, 0x1, 0xe) => Instruction::AddI(x),
             (0xf, x, 0x2, 0x9) => Instruction::LoadSprite(x),
+            (0xf, x, 0x3, 0x0) => Instruction::LoadBigSprite(x),
             (0xf, x, 0x3, 0x3) => Instruction::LoadBCD(x),
             (0xf, x, 0x5, 0x5) => Instruction::LoadAllI(x),
             (0xf, x, 0x6, 0x5) => Instruction::SetAllI(x),
+            (0xf, x, 0x7, 0x5) => Instruction::StoreFlags(x),
+            (0xf, x, 0x8, 0x5) => Instruction::LoadFlags(x),
+            (0xf, n, 0x0, 0x1) => Instruction::SelectPlanes(n),
+            (0xf, 0x0, 0x0, 0x2) => Instruction::LoadAudio,
+            (0xf, x, 0x3, 0xa) => Instruction::SetPitch(x),
             (0x0, n1, n2, n3) => Instruction::Sys(nnn(n1, n2, n3)),
             (0x1, n1, n2, n3) => Instruction::Jmp(nnn(n1, n2, n3)),
             (0x2, n1, n2, n3) => Instruction::Call(nnn(n1, n2, n3)),
-            _ => unreachable!(),
+            _ => {
+                return Err(DecodeError {
+                    opcode: ins,
+                    pc: None,
+                })
+            }
+        };
+
+        Ok(ins)
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Instruction::Sys(nnn) => write!(f, "SYS 0x{nnn:03X}"),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::ScrollDown(n) => write!(f, "SCD {n}"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::Jmp(nnn) => write!(f, "JP 0x{nnn:03X}"),
+            Instruction::Call(nnn) => write!(f, "CALL 0x{nnn:03X}"),
+            Instruction::SkipEq(x, kk) => write!(f, "SE V{x:X}, 0x{kk:02X}"),
+            Instruction::SkipNEq(x, kk) => write!(f, "SNE V{x:X}, 0x{kk:02X}"),
+            Instruction::SkipEqV(x, y) => write!(f, "SE V{x:X}, V{y:X}"),
+            Instruction::Set(x, kk) => write!(f, "LD V{x:X}, 0x{kk:02X}"),
+            Instruction::Add(x, kk) => write!(f, "ADD V{x:X}, 0x{kk:02X}"),
+            Instruction::Load(x, y) => write!(f, "LD V{x:X}, V{y:X}"),
+            Instruction::Or(x, y) => write!(f, "OR V{x:X}, V{y:X}"),
+            Instruction::And(x, y) => write!(f, "AND V{x:X}, V{y:X}"),
+            Instruction::Xor(x, y) => write!(f, "XOR V{x:X}, V{y:X}"),
+            Instruction::AddCarry(x, y) => write!(f, "ADD V{x:X}, V{y:X}"),
+            Instruction::SubCarry(x, y) => write!(f, "SUB V{x:X}, V{y:X}"),
+            Instruction::Shr(x, y) => write!(f, "SHR V{x:X}, V{y:X}"),
+            Instruction::SubN(x, y) => write!(f, "SUBN V{x:X}, V{y:X}"),
+            Instruction::Shl(x, y) => write!(f, "SHL V{x:X}, V{y:X}"),
+            Instruction::Sne(x, y) => write!(f, "SNE V{x:X}, V{y:X}"),
+            Instruction::LoadI(nnn) => write!(f, "LD I, 0x{nnn:03X}"),
+            Instruction::JmpV0(nnn) => write!(f, "JP V0, 0x{nnn:03X}"),
+            Instruction::Rnd(x, kk) => write!(f, "RND V{x:X}, 0x{kk:02X}"),
+            Instruction::Drw(x, y, n) => write!(f, "DRW V{x:X}, V{y:X}, {n}"),
+            Instruction::SkipPressed(x) => write!(f, "SKP V{x:X}"),
+            Instruction::SkipNPressed(x) => write!(f, "SKNP V{x:X}"),
+            Instruction::LoadDT(x) => write!(f, "LD V{x:X}, DT"),
+            Instruction::LoadKeyPress(x) => write!(f, "LD V{x:X}, K"),
+            Instruction::SetDT(x) => write!(f, "LD DT, V{x:X}"),
+            Instruction::SetST(x) => write!(f, "LD ST, V{x:X}"),
+            Instruction::AddI(x) => write!(f, "ADD I, V{x:X}"),
+            Instruction::LoadSprite(x) => write!(f, "LD F, V{x:X}"),
+            Instruction::LoadBigSprite(x) => write!(f, "LD HF, V{x:X}"),
+            Instruction::LoadBCD(x) => write!(f, "LD B, V{x:X}"),
+            Instruction::LoadAllI(x) => write!(f, "LD [I], V{x:X}"),
+            Instruction::SetAllI(x) => write!(f, "LD V{x:X}, [I]"),
+            Instruction::StoreFlags(x) => write!(f, "LD R, V{x:X}"),
+            Instruction::LoadFlags(x) => write!(f, "LD V{x:X}, R"),
+            Instruction::SelectPlanes(n) => write!(f, "PLANE {n}"),
+            Instruction::LoadAudio => write!(f, "AUDIO"),
+            Instruction::SetPitch(x) => write!(f, "PITCH V{x:X}"),
+            Instruction::LongLoadI(nnnn) => write!(f, "LD I, long 0x{nnnn:04X}"),
         }
     }
 }
@@ -290,9 +603,21 @@ pub fn kk(k1: U4, k2: U4) -> u8 {
     k1 << 4 | k2
 }
 
+fn op_nnn(op: U4, nnn: U12) -> u16 {
+    (op as u16) << 12 | nnn & 0xfff
+}
+
+fn op_xkk(op: U4, x: U4, kk: u8) -> u16 {
+    (op as u16) << 12 | ((x & 0xf) as u16) << 8 | kk as u16
+}
+
+fn op_xyn(op: U4, x: U4, y: U4, n: U4) -> u16 {
+    op_xkk(op, x, (y & 0xf) << 4 | n & 0xf)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::instructions::{kk, nnn, u16_to_nibbles};
+    use crate::instructions::{kk, nnn, u16_to_nibbles, DecodeError, Instruction};
 
     #[test]
     fn test_u16_to_nibbles() {
@@ -311,4 +636,147 @@ mod tests {
         let r = kk(0xf, 0xd);
         assert_eq!(r, 0xfd);
     }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            Instruction::try_from(0xd125),
+            Ok(Instruction::Drw(0x1, 0x2, 0x5))
+        );
+    }
+
+    #[test]
+    fn test_decode_unchecked() {
+        assert_eq!(
+            Instruction::decode_unchecked(0xd125),
+            Instruction::Drw(0x1, 0x2, 0x5)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_decode_unchecked_unknown() {
+        Instruction::decode_unchecked(0x5121);
+    }
+
+    #[test]
+    fn test_decode_big_sprite() {
+        let ins = Instruction::try_from(0xf730).unwrap();
+        assert_eq!(ins, Instruction::LoadBigSprite(0x7));
+        assert_eq!(ins.to_string(), "LD HF, V7");
+    }
+
+    #[test]
+    fn test_decode_flags() {
+        assert_eq!(
+            Instruction::try_from(0xf375),
+            Ok(Instruction::StoreFlags(0x3))
+        );
+        assert_eq!(
+            Instruction::try_from(0xf585),
+            Ok(Instruction::LoadFlags(0x5))
+        );
+        assert_eq!(Instruction::StoreFlags(0x3).to_string(), "LD R, V3");
+        assert_eq!(Instruction::LoadFlags(0x5).to_string(), "LD V5, R");
+    }
+
+    #[test]
+    fn test_decode_audio() {
+        assert_eq!(Instruction::try_from(0xf002), Ok(Instruction::LoadAudio));
+        assert_eq!(
+            Instruction::try_from(0xf53a),
+            Ok(Instruction::SetPitch(0x5))
+        );
+        assert_eq!(Instruction::LoadAudio.to_string(), "AUDIO");
+        assert_eq!(Instruction::SetPitch(0x5).to_string(), "PITCH V5");
+
+        // Only F002 loads the pattern, there's no register in it.
+        assert!(Instruction::try_from(0xf102).is_err());
+    }
+
+    #[test]
+    fn test_decode_long_load() {
+        let ins = Instruction::decode(0xf000, 0x1234).unwrap();
+        assert_eq!(ins, Instruction::LongLoadI(0x1234));
+        assert_eq!(ins.size(), 4);
+        assert_eq!(ins.to_string(), "LD I, long 0x1234");
+
+        // Without the address word F000 means nothing.
+        assert!(Instruction::try_from(0xf000).is_err());
+        assert_eq!(
+            Instruction::decode(0xd125, 0x1234),
+            Ok(Instruction::Drw(0x1, 0x2, 0x5))
+        );
+        assert_eq!(Instruction::Drw(0x1, 0x2, 0x5).size(), 2);
+    }
+
+    #[test]
+    fn test_decode_is_total() {
+        for word in 0..=u16::MAX {
+            match Instruction::try_from(word) {
+                Ok(ins) => assert!(!ins.to_string().is_empty()),
+                Err(e) => assert_eq!(e.opcode, word),
+            }
+        }
+
+        // The gaps between the defined cases in each group.
+        for word in [0x5121, 0x8128, 0x912f, 0xe1ff, 0xf1ff] {
+            assert!(Instruction::try_from(word).is_err(), "{word:#06x}");
+        }
+    }
+
+    #[test]
+    fn test_encode_round_trips() {
+        for word in 0..=u16::MAX {
+            if let Ok(ins) = Instruction::try_from(word) {
+                assert_eq!(ins.encode(), word, "{ins}");
+            }
+        }
+
+        let ins = Instruction::decode(0xf000, 0xbeef).unwrap();
+        assert_eq!(ins.encode(), 0xf000);
+    }
+
+    #[test]
+    fn test_encode_truncates_operands() {
+        assert_eq!(Instruction::Jmp(0x1234).encode(), 0x1234);
+        assert_eq!(Instruction::Drw(0x12, 0x3, 0x45).encode(), 0xd235);
+    }
+
+    #[test]
+    fn test_decode_unknown_opcode() {
+        let r = Instruction::try_from(0x5121);
+        assert_eq!(
+            r,
+            Err(DecodeError {
+                opcode: 0x5121,
+                pc: None
+            })
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let cases = [
+            (0x00e0, "CLS"),
+            (0x00ff, "HIGH"),
+            (0x00fe, "LOW"),
+            (0x00c4, "SCD 4"),
+            (0x00fb, "SCR"),
+            (0x00fc, "SCL"),
+            (0x1200, "JP 0x200"),
+            (0x2abc, "CALL 0xABC"),
+            (0x631f, "LD V3, 0x1F"),
+            (0x8ab4, "ADD VA, VB"),
+            (0xb123, "JP V0, 0x123"),
+            (0xd015, "DRW V0, V1, 5"),
+            (0xfe0a, "LD VE, K"),
+            (0xf255, "LD [I], V2"),
+            (0xf301, "PLANE 3"),
+        ];
+
+        for (opcode, asm) in cases {
+            assert_eq!(Instruction::try_from(opcode).unwrap().to_string(), asm);
+        }
+    }
 }
@@ -0,0 +1,116 @@
+// IPS patches, the format most ROM hacks are shared in.
+//
+// A patch is the `PATCH` header, then records until the `EOF` marker. Each record is a 3 byte
+// big-endian offset and a 2 byte size followed by that many bytes to write at the offset. A
+// size of 0 marks an RLE record instead: a 2 byte count and the one byte to repeat. Records
+// past the end of the ROM grow it, zero filling any gap. Some patchers append a 3 byte size
+// after `EOF` to truncate the result to.
+
+use std::io;
+
+const HEADER: &[u8] = b"PATCH";
+const EOF: &[u8] = b"EOF";
+
+/// Returns `rom` with every record of `patch` applied, in order.
+pub fn apply(rom: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+    let mut records = patch
+        .strip_prefix(HEADER)
+        .ok_or_else(|| invalid("missing PATCH header"))?;
+    let mut patched = rom.to_vec();
+
+    loop {
+        let offset = take(&mut records, 3)?;
+        if offset == EOF {
+            match records.len() {
+                0 => {}
+                3 => patched.truncate(be(records)),
+                _ => return Err(invalid("unexpected data after EOF")),
+            }
+            return Ok(patched);
+        }
+        let offset = be(offset);
+
+        let size = be(take(&mut records, 2)?);
+        let (len, fill) = if size == 0 {
+            let count = be(take(&mut records, 2)?);
+            if count == 0 {
+                return Err(invalid(&format!("empty RLE record at {offset:#x}")));
+            }
+            (count, Some(take(&mut records, 1)?[0]))
+        } else {
+            (size, None)
+        };
+
+        let end = offset + len;
+        if end > patched.len() {
+            patched.resize(end, 0);
+        }
+        match fill {
+            Some(byte) => patched[offset..end].fill(byte),
+            None => patched[offset..end].copy_from_slice(take(&mut records, len)?),
+        }
+    }
+}
+
+/// Splits the first `n` bytes off `bytes`, failing if there aren't that many.
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if bytes.len() < n {
+        return Err(invalid("truncated record, or missing EOF"));
+    }
+    let (head, tail) = bytes.split_at(n);
+    *bytes = tail;
+    Ok(head)
+}
+
+fn be(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |n, &b| n << 8 | b as usize)
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("bad IPS patch: {what}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ips::apply;
+
+    #[test]
+    fn test_apply() {
+        let rom = [0x00, 0xe0, 0x12, 0x00];
+        let patch = [
+            b"PATCH".as_slice(),
+            // Two bytes at 0x0002.
+            &[0x00, 0x00, 0x02, 0x00, 0x02, 0x13, 0x4e],
+            // Three 0xaa at 0x0006, past the end.
+            &[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x03, 0xaa],
+            b"EOF",
+        ]
+        .concat();
+
+        assert_eq!(
+            apply(&rom, &patch).unwrap(),
+            [0x00, 0xe0, 0x13, 0x4e, 0x00, 0x00, 0xaa, 0xaa, 0xaa]
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        let patch = [b"PATCH".as_slice(), b"EOF", &[0x00, 0x00, 0x02]].concat();
+        assert_eq!(apply(&[1, 2, 3, 4], &patch).unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let rom = [0; 4];
+        // No header.
+        assert!(apply(&rom, b"EOF").is_err());
+        // No EOF.
+        assert!(apply(&rom, b"PATCH\x00\x00\x00\x00\x01\xff").is_err());
+        // Fewer bytes than the record's size.
+        assert!(apply(&rom, b"PATCH\x00\x00\x00\x00\x10\xffEOF").is_err());
+        // An RLE record that repeats nothing.
+        assert!(apply(&rom, b"PATCH\x00\x00\x00\x00\x00\x00\x00\xffEOF").is_err());
+        // Junk after EOF.
+        assert!(apply(&rom, b"PATCHEOF\x01").is_err());
+    }
+}
@@ -3,37 +3,116 @@
 // 7	8	9	E
 // A	0	B	F
 
-
+/// The hex keypad as laid out on the COSMAC VIP, row by row.
+pub const KEYPAD: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
 
 pub struct Keyboard {
     keys: [bool; 16],
+    previous: [bool; 16],
 }
 
 impl Keyboard {
     pub fn new() -> Self {
-        Keyboard { keys: [false; 16] }
+        Keyboard {
+            keys: [false; 16],
+            previous: [false; 16],
+        }
+    }
+
+    /// Remembers the current key states, edges are reported relative to the last `tick`.
+    /// Call once per frame.
+    pub fn tick(&mut self) {
+        self.previous = self.keys;
+    }
+
+    pub fn just_pressed(&self, n: usize) -> bool {
+        self.keys[n] && !self.previous[n]
+    }
+
+    pub fn just_released(&self, n: usize) -> bool {
+        !self.keys[n] && self.previous[n]
     }
 
     pub fn is_pressed(&self, n: usize) -> bool {
-        println!("checking for key {n:}");
         self.keys[n]
     }
 
-    pub fn get_pressed(&self) -> Option<u8> {
-        for i in 0..16 {
-            if self.keys[i] {
-                return Some(i as u8);
-            }
-        }
+    /// Whether each key is down, indexed by key.
+    pub fn keys(&self) -> [bool; 16] {
+        self.keys
+    }
 
-        None
+    /// The first key that went down since the last `tick`, if any.
+    pub fn get_just_pressed(&self) -> Option<u8> {
+        (0..16).find(|&i| self.just_pressed(i)).map(|i| i as u8)
     }
 
+    /// Marks the key as down. Pressing a key that's already down does nothing, so the OS
+    /// repeating presses while a key is held can't make a second `just_pressed` edge.
     pub fn press(&mut self, n: usize) {
         self.keys[n] = true
     }
 
+    /// Marks the key as up, like `press` repeating it does nothing.
     pub fn release(&mut self, n: usize) {
         self.keys[n] = false
     }
 }
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keyboard::Keyboard;
+
+    #[test]
+    fn test_edges() {
+        let mut keyboard = Keyboard::new();
+
+        keyboard.press(5);
+        assert!(keyboard.just_pressed(5));
+        assert_eq!(keyboard.get_just_pressed(), Some(5));
+
+        keyboard.tick();
+        assert!(keyboard.is_pressed(5));
+        assert!(!keyboard.just_pressed(5));
+        assert_eq!(keyboard.get_just_pressed(), None);
+
+        keyboard.release(5);
+        assert!(keyboard.just_released(5));
+
+        keyboard.tick();
+        assert!(!keyboard.just_released(5));
+    }
+
+    #[test]
+    fn test_repeated_press() {
+        let mut keyboard = Keyboard::new();
+
+        keyboard.press(5);
+        keyboard.press(5);
+        assert_eq!(keyboard.get_just_pressed(), Some(5));
+
+        // Auto-repeat while the key stays held, in the next frame.
+        keyboard.tick();
+        keyboard.press(5);
+        assert!(!keyboard.just_pressed(5));
+        assert_eq!(keyboard.get_just_pressed(), None);
+
+        keyboard.release(5);
+        keyboard.release(5);
+        assert!(keyboard.just_released(5));
+        keyboard.tick();
+        keyboard.release(5);
+        assert!(!keyboard.just_released(5));
+    }
+}
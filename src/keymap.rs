@@ -0,0 +1,169 @@
+use chip8::keyboard::{Keyboard, KEYPAD};
+use piston_window::Key;
+use std::collections::HashMap;
+
+/// Maps host keys to the CHIP-8 keys they press. One host key can press several CHIP-8 keys.
+pub struct KeyMap {
+    keys: HashMap<Key, Binding>,
+}
+
+struct Binding {
+    chip8_keys: Vec<u8>,
+    pulse: bool,
+}
+
+impl KeyMap {
+    /// An empty map, with nothing bound.
+    pub fn new() -> Self {
+        KeyMap {
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Binds `key` to `chip8_keys`, replacing whatever it was bound to.
+    pub fn bind(&mut self, key: Key, chip8_keys: Vec<u8>) {
+        let pulse = false;
+        self.keys.insert(key, Binding { chip8_keys, pulse });
+    }
+
+    /// Like `bind`, but pressing `key` only taps `chip8_keys` for a frame however long it's
+    /// held, see `Pulses`. Handy for menus that scroll while a key is down.
+    pub fn bind_pulse(&mut self, key: Key, chip8_keys: Vec<u8>) {
+        let pulse = true;
+        self.keys.insert(key, Binding { chip8_keys, pulse });
+    }
+
+    /// The CHIP-8 keys pressed by `key`, empty when it isn't bound.
+    pub fn get(&self, key: Key) -> &[u8] {
+        self.keys
+            .get(&key)
+            .map_or(&[], |binding| binding.chip8_keys.as_slice())
+    }
+
+    pub fn is_pulse(&self, key: Key) -> bool {
+        self.keys.get(&key).is_some_and(|binding| binding.pulse)
+    }
+}
+
+/// CHIP-8 keys pressed by pulse bindings, held down for one whole frame and then released.
+#[derive(Default)]
+pub struct Pulses {
+    // Pressed since the last frame.
+    pressed: Vec<u8>,
+    // Down for the current frame, released at the next one.
+    held: Vec<u8>,
+}
+
+impl Pulses {
+    pub fn press(&mut self, keyboard: &mut Keyboard, chip8_keys: &[u8]) {
+        for &k in chip8_keys {
+            keyboard.press(k as usize);
+            self.pressed.push(k);
+        }
+    }
+
+    /// Call once per rendered frame.
+    pub fn frame(&mut self, keyboard: &mut Keyboard) {
+        for k in self.held.drain(..) {
+            // Pressed again in the meantime, that pulse gets its own frame.
+            if !self.pressed.contains(&k) {
+                keyboard.release(k as usize);
+            }
+        }
+        std::mem::swap(&mut self.held, &mut self.pressed);
+    }
+}
+
+impl Default for KeyMap {
+    /// The de-facto standard layout, the left side of a QWERTY keyboard stands in for the
+    /// hex keypad:
+    ///
+    /// ```text
+    /// 1 2 3 4        1 2 3 C
+    /// Q W E R   ->   4 5 6 D
+    /// A S D F        7 8 9 E
+    /// Z X C V        A 0 B F
+    /// ```
+    fn default() -> Self {
+        let grid = [
+            [Key::D1, Key::D2, Key::D3, Key::D4],
+            [Key::Q, Key::W, Key::E, Key::R],
+            [Key::A, Key::S, Key::D, Key::F],
+            [Key::Z, Key::X, Key::C, Key::V],
+        ];
+
+        let mut map = KeyMap::new();
+        for (keys, chip8_keys) in grid.iter().zip(KEYPAD.iter()) {
+            for (&key, &chip8_key) in keys.iter().zip(chip8_keys.iter()) {
+                map.bind(key, vec![chip8_key]);
+            }
+        }
+
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keymap::{KeyMap, Pulses};
+    use chip8::keyboard::Keyboard;
+    use piston_window::Key;
+
+    #[test]
+    fn test_default_layout() {
+        let map = KeyMap::default();
+
+        assert_eq!(map.get(Key::D1), &[0x1]);
+        assert_eq!(map.get(Key::D4), &[0xc]);
+        assert_eq!(map.get(Key::Q), &[0x4]);
+        assert_eq!(map.get(Key::R), &[0xd]);
+        assert_eq!(map.get(Key::S), &[0x8]);
+        assert_eq!(map.get(Key::X), &[0x0]);
+        assert_eq!(map.get(Key::V), &[0xf]);
+        assert_eq!(map.get(Key::Space), &[] as &[u8]);
+    }
+
+    #[test]
+    fn test_rebind() {
+        let mut map = KeyMap::default();
+
+        map.bind(Key::Up, vec![1, 2]);
+        map.bind(Key::D1, vec![]);
+
+        assert_eq!(map.get(Key::Up), &[1, 2]);
+        assert_eq!(map.get(Key::D1), &[] as &[u8]);
+        assert!(!map.is_pulse(Key::Up));
+    }
+
+    #[test]
+    fn test_pulse_combo() {
+        let mut map = KeyMap::default();
+        map.bind_pulse(Key::Return, vec![0x5, 0x6]);
+        assert_eq!(map.get(Key::Return), &[0x5, 0x6]);
+        assert!(map.is_pulse(Key::Return));
+
+        let mut keyboard = Keyboard::new();
+        let mut pulses = Pulses::default();
+        pulses.press(&mut keyboard, map.get(Key::Return));
+        assert!(keyboard.is_pressed(0x5) && keyboard.is_pressed(0x6));
+
+        // Held through the rest of this frame and the next one.
+        pulses.frame(&mut keyboard);
+        assert!(keyboard.is_pressed(0x5) && keyboard.is_pressed(0x6));
+        pulses.frame(&mut keyboard);
+        assert!(!keyboard.is_pressed(0x5) && !keyboard.is_pressed(0x6));
+    }
+
+    #[test]
+    fn test_default_layout_covers_keypad() {
+        let map = KeyMap::default();
+
+        let mut covered: Vec<u8> = map
+            .keys
+            .values()
+            .flat_map(|binding| binding.chip8_keys.iter().copied())
+            .collect();
+        covered.sort();
+        assert_eq!(covered, (0..16).collect::<Vec<u8>>());
+    }
+}
@@ -0,0 +1,74 @@
+use chip8::keyboard::KEYPAD;
+
+/// The space between keys, as a fraction of a key's cell.
+const GAP: f64 = 0.1;
+
+/// Where the on-screen keypad sits in the window: a square `size` pixels wide with its top
+/// left corner at `(left, top)`, split into a 4x4 grid of keys laid out like `KEYPAD`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeypadLayout {
+    pub left: f64,
+    pub top: f64,
+    pub size: f64,
+}
+
+impl KeypadLayout {
+    /// Every key with the `[x, y, width, height]` of its button.
+    pub fn keys(&self) -> impl Iterator<Item = (u8, [f64; 4])> + '_ {
+        let cell = self.size / 4.0;
+        let gap = cell * GAP;
+        KEYPAD.iter().enumerate().flat_map(move |(row, keys)| {
+            keys.iter().enumerate().map(move |(column, &key)| {
+                let x = self.left + column as f64 * cell + gap / 2.0;
+                let y = self.top + row as f64 * cell + gap / 2.0;
+                (key, [x, y, cell - gap, cell - gap])
+            })
+        })
+    }
+
+    /// The key whose button is under `pos`, `None` in the gaps or outside the keypad.
+    pub fn key_at(&self, pos: [f64; 2]) -> Option<u8> {
+        let [x, y] = pos;
+        self.keys()
+            .find(|(_, [left, top, width, height])| {
+                x >= *left && x < left + width && y >= *top && y < top + height
+            })
+            .map(|(key, _)| key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keypad::KeypadLayout;
+
+    #[test]
+    fn test_key_at() {
+        let layout = KeypadLayout {
+            left: 100.0,
+            top: 20.0,
+            size: 40.0,
+        };
+
+        assert_eq!(layout.key_at([105.0, 25.0]), Some(0x1));
+        assert_eq!(layout.key_at([135.0, 25.0]), Some(0xc));
+        assert_eq!(layout.key_at([115.0, 55.0]), Some(0x0));
+        assert_eq!(layout.key_at([135.0, 55.0]), Some(0xf));
+
+        // Between two keys and off the keypad.
+        assert_eq!(layout.key_at([110.0, 25.0]), None);
+        assert_eq!(layout.key_at([99.0, 25.0]), None);
+        assert_eq!(layout.key_at([105.0, 61.0]), None);
+    }
+
+    #[test]
+    fn test_keys_cover_the_keypad() {
+        let layout = KeypadLayout {
+            left: 0.0,
+            top: 0.0,
+            size: 100.0,
+        };
+        let mut keys: Vec<_> = layout.keys().map(|(key, _)| key).collect();
+        keys.sort();
+        assert_eq!(keys, (0..16).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,44 @@
+//! A CHIP-8, SUPER-CHIP and XO-CHIP interpreter.
+//!
+//! The core is `Machine`, which runs instructions against a `Screen` and reads a `Keyboard`.
+//! It has no windowing or audio of its own; the desktop frontend lives in the `chip8` binary
+//! behind the `window` feature.
+//!
+//! ```
+//! use chip8::{Keyboard, Machine, Quirks, Screen};
+//! use chip8::roms::BuiltinRom;
+//!
+//! let mut machine = Machine::new(Quirks::default());
+//! let mut screen = Screen::new();
+//! machine.load_builtin(BuiltinRom::IbmLogo).unwrap();
+//! machine.run_headless(100, &Keyboard::new(), &mut screen).unwrap();
+//! assert!(screen.is_set(12, 8));
+//! ```
+
+pub mod analysis;
+pub mod cost;
+pub mod diff;
+pub mod instructions;
+pub mod ips;
+pub mod keyboard;
+pub mod machine;
+pub mod memory;
+pub mod palette;
+pub mod profiles;
+pub mod quirk_test;
+pub mod quirks;
+pub mod recorder;
+pub mod render;
+pub mod replay;
+pub mod roms;
+pub mod screen;
+pub mod sound;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use instructions::{DecodeError, Instruction};
+pub use keyboard::Keyboard;
+pub use machine::{Machine, MachineError, StepOutcome};
+pub use memory::Memory;
+pub use quirks::Quirks;
+pub use screen::Screen;
@@ -1,87 +1,1072 @@
-use crate::instructions::{Instruction};
+use crate::analysis::{analyze, Analysis};
+use crate::cost::CostModel;
+use crate::instructions::{DecodeError, Instruction};
+use crate::ips;
 use crate::keyboard::Keyboard;
-use crate::screen::Screen;
-use rand::random;
+use crate::memory::Memory;
+use crate::profiles::Profiles;
+use crate::quirks::Quirks;
+use crate::roms::BuiltinRom;
+use crate::screen::{Screen, PLANES};
+use crate::sound::{NullSound, Sound, DEFAULT_PITCH, PATTERN_BYTES};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read};
+use std::ops::Range;
+#[cfg(not(feature = "wasm"))]
+use std::time::Instant;
+// std's Instant panics in the browser.
+#[cfg(feature = "wasm")]
+use web_time::Instant;
 
 const PROGRAM_START_AT: usize = 0x200;
+/// Where the hex digit sprites live, 0x050 like most other interpreters.
+pub const FONT_START: usize = 0x050;
+/// Where the large SUPER-CHIP digits live, straight after the small ones.
+pub const BIG_FONT_START: usize = FONT_START + 5 * 16;
+/// Both fonts, what `Machine::protect_font` guards.
+pub const FONT_AREA: Range<usize> = FONT_START..BIG_FONT_START + 10 * 16;
+/// The classic 4KB of RAM.
+pub const RAM_SIZE: usize = 4096;
+/// The 64KB of RAM in XO-CHIP mode, see `Machine::set_xo_chip`.
+pub const XO_RAM_SIZE: usize = 0x10000;
 const TIMER_RATE: u64 = 16666; // 60 Hz
+const DEFAULT_CLOCK_HZ: u32 = 540;
 
-pub struct Machine {
-    ram: [u8; 4098],
+/// A fault raised by `Machine::step`. Faulting instructions are skipped, so the host can
+/// report them and keep stepping, except when the instruction can't even be fetched: the PC
+/// stays put and every step reports the same `OutOfBounds`. `Machine::load_state` also uses
+/// it to refuse a bad state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineError {
+    Decode(DecodeError),
+    /// A CALL at `pc` with all 16 stack slots in use.
+    StackOverflow {
+        pc: usize,
+    },
+    /// A RET at `pc` with nothing on the stack.
+    StackUnderflow {
+        pc: usize,
+    },
+    /// An access to `addr`, past the end of memory. When this comes from fetching an
+    /// instruction the PC is left where it was.
+    OutOfBounds {
+        addr: usize,
+    },
+    /// A jump, call or return at `pc` to the odd address `target`, with strict alignment on.
+    MisalignedJump {
+        pc: usize,
+        target: usize,
+    },
+    /// A store to `addr`, which is write protected. Nothing was written.
+    WriteProtected {
+        addr: usize,
+    },
+    /// A `MachineState` that no machine could have saved, e.g. read from a corrupt file.
+    InvalidState {
+        reason: &'static str,
+    },
+}
+
+impl From<DecodeError> for MachineError {
+    fn from(e: DecodeError) -> Self {
+        MachineError::Decode(e)
+    }
+}
+
+impl Display for MachineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineError::Decode(e) => write!(f, "{e}"),
+            MachineError::StackOverflow { pc } => write!(f, "stack overflow at {pc:#05x}"),
+            MachineError::StackUnderflow { pc } => write!(f, "stack underflow at {pc:#05x}"),
+            MachineError::OutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {addr:#05x}")
+            }
+            MachineError::MisalignedJump { pc, target } => {
+                write!(f, "jump at {pc:#05x} to odd address {target:#05x}")
+            }
+            MachineError::WriteProtected { addr } => {
+                write!(f, "write to protected address {addr:#05x}")
+            }
+            MachineError::InvalidState { reason } => write!(f, "invalid machine state: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for MachineError {}
+
+/// What happened during a successful `Machine::step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction was executed.
+    Ok,
+    /// The PC reached a breakpoint at this address, nothing was executed. The next `step`
+    /// runs the instruction.
+    BreakpointHit(usize),
+    /// The machine is paused, nothing was executed.
+    Paused,
+    /// The instruction that just ran accessed a watched address, the access is `Read` or
+    /// `Write`. Only the first hit of an instruction is reported.
+    WatchpointHit(usize, WatchKind),
+    /// A jump at this address to itself was executed, the program will spin there forever
+    /// unless something else (a timer, a key) is being waited on.
+    Halted(usize),
+}
+
+/// Which memory accesses a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(self, access: WatchKind) -> bool {
+        self == WatchKind::ReadWrite || self == access
+    }
+}
+
+/// What counts the delay and sound timers down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimingMode {
+    /// 60 times a second of wall-clock time, however many steps that takes.
+    #[default]
+    RealTime,
+    /// Once every `cycles_per_frame` steps, so runs are reproducible whatever the host's
+    /// timing. A step waiting for the display with `display_wait` counts too.
+    Lockstep { cycles_per_frame: u32 },
+    /// Only when the host calls `tick`, for frontends with their own frame clock.
+    Manual,
+}
+
+/// What the last Dxyn erased, see `Machine::last_draw_collisions`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrawCollisions {
+    /// Screen pixels erased in at least one plane.
+    pub pixels: usize,
+    /// The planes anything was erased in, one bit per plane.
+    pub planes: u8,
+}
+
+/// Everything needed to resume a machine (and its screen) exactly where it was saved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineState {
+    pub ram: Vec<u8>,
+    pub registers: [u8; 16],
+    pub register_i: u16,
+    pub register_delay: u8,
+    pub register_sound: u8,
+    pub pc: usize,
+    pub sp: usize,
+    pub stack: [u16; 16],
+    pub screen: Vec<u8>,
+    pub screen_size: (usize, usize),
+    pub selected_planes: u8,
+}
+
+/// Called with the PC, the raw opcode and the decoded instruction, right before it runs.
+pub type TraceHook = Box<dyn FnMut(usize, u16, &Instruction)>;
+
+/// Called with `true` when the sound timer goes from zero to running and `false` when it runs
+/// out, at the same moments the `Sound` backend is started and stopped.
+pub type SoundHook = Box<dyn FnMut(bool)>;
+
+pub struct Machine<M: Memory = Vec<u8>> {
+    ram: M,
     registers: [u8; 16],
     register_i: u16,
     register_delay: u8,
     register_sound: u8,
-    last_tick: std::time::Instant,
+    last_tick: Instant,
+    step_interval: std::time::Duration,
     pc: usize,
     sp: usize,
     stack: [u16; 16],
+    sound: Box<dyn Sound>,
+    beeping: bool,
+    audio_pattern: Option<[u8; PATTERN_BYTES]>,
+    pitch: u8,
+    quirks: Quirks,
+    waiting_key: Option<u8>,
+    breakpoints: HashSet<usize>,
+    at_breakpoint: bool,
+    watchpoints: HashMap<usize, WatchKind>,
+    watch_hit: Option<(usize, WatchKind)>,
+    protected: Vec<Range<usize>>,
+    paused: bool,
+    waiting_vblank: bool,
+    cycles: u64,
+    xo_chip: bool,
+    strict_alignment: bool,
+    wrap_memory: bool,
+    timing_mode: TimingMode,
+    frame_cycles: u32,
+    entry_point: usize,
+    last_draw: DrawCollisions,
+    trace_hook: Option<TraceHook>,
+    sound_hook: Option<SoundHook>,
+    rng: StdRng,
+    timer_speed: u32,
+    history: VecDeque<MachineState>,
+    history_limit: usize,
+    cost_model: CostModel,
+    last_cost: u32,
+    rpl_flags: [u8; 8],
+    decode_cache: Option<Vec<Option<Instruction>>>,
+    opcode_counts: Option<HashMap<&'static str, u64>>,
+    // Kept for `reset`.
+    rom: Vec<u8>,
+    font: [u8; 80],
 }
 
 impl Machine {
-    pub fn new() -> Self {
+    pub fn new(quirks: Quirks) -> Self {
+        Machine::with_memory(quirks, vec![0; RAM_SIZE])
+    }
+}
+
+impl<M: Memory> Machine<M> {
+    /// A machine running on `memory`, resized to the 4KB of classic RAM.
+    pub fn with_memory(quirks: Quirks, mut memory: M) -> Self {
+        memory.resize(RAM_SIZE);
         let mut m = Machine {
-            ram: [0; 4098],
+            ram: memory,
             registers: [0; 16],
             register_i: 0,
             register_delay: 0,
             register_sound: 0,
-            last_tick: std::time::Instant::now(),
+            last_tick: Instant::now(),
+            step_interval: clock_interval(DEFAULT_CLOCK_HZ),
             pc: PROGRAM_START_AT,
             sp: 0,
             stack: [0; 16],
+            sound: Box::new(NullSound),
+            beeping: false,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            quirks,
+            waiting_key: None,
+            breakpoints: HashSet::new(),
+            at_breakpoint: false,
+            watchpoints: HashMap::new(),
+            watch_hit: None,
+            protected: Vec::new(),
+            paused: false,
+            waiting_vblank: false,
+            cycles: 0,
+            xo_chip: false,
+            strict_alignment: false,
+            wrap_memory: false,
+            timing_mode: TimingMode::RealTime,
+            frame_cycles: 0,
+            entry_point: PROGRAM_START_AT,
+            last_draw: DrawCollisions::default(),
+            trace_hook: None,
+            sound_hook: None,
+            rng: StdRng::from_entropy(),
+            timer_speed: 1,
+            history: VecDeque::new(),
+            history_limit: 0,
+            cost_model: CostModel::default(),
+            last_cost: 0,
+            rpl_flags: [0; 8],
+            decode_cache: None,
+            opcode_counts: None,
+            rom: Vec::new(),
+            font: NUMBERS,
         };
 
-        m.ram[..(5 * 16)].copy_from_slice(&NUMBERS);
+        m.load_fonts();
 
         m
     }
 
-    pub fn load(&mut self, rom: &[u8]) {
-        let start = self.pc;
-        let end = start + rom.len();
-        self.ram[start..end].copy_from_slice(rom);
+    /// Replaces the hex digit sprites Fx29 points I at, five bytes per digit from 0 to F.
+    pub fn set_font(&mut self, font: &[u8; 80]) {
+        self.font = *font;
+        self.load_fonts();
+    }
+
+    fn load_fonts(&mut self) {
+        self.ram[FONT_START..FONT_START + self.font.len()].copy_from_slice(&self.font);
+        self.ram[BIG_FONT_START..BIG_FONT_START + BIG_NUMBERS.len()].copy_from_slice(&BIG_NUMBERS);
+        self.invalidate_decoded(FONT_START, self.font.len() + BIG_NUMBERS.len());
     }
 
-    pub fn step(&mut self, keyboard: &Keyboard, screen: &mut Screen) {
-        let ins: u16 = ((self.ram[self.pc] as usize) << 8 | self.ram[self.pc + 1] as usize) as u16;
+    /// Restarts the last loaded ROM as if it was just loaded: memory is wiped and the ROM
+    /// copied back in, the registers, stack and timers are cleared and the screen goes back
+    /// to a blank low resolution display.
+    ///
+    /// Settings survive: quirks, clock, breakpoints, watchpoints, hooks and the RPL flags.
+    pub fn reset(&mut self, screen: &mut Screen) {
+        self.ram.fill(0);
+        self.set_decode_cache(self.decode_cache.is_some());
+        self.load_fonts();
+        let end = self.entry_point + self.rom.len();
+        self.ram[self.entry_point..end].copy_from_slice(&self.rom);
 
-        self.pc += 2;
+        self.registers = [0; 16];
+        self.register_i = 0;
+        self.register_delay = 0;
+        self.register_sound = 0;
+        self.pc = self.entry_point;
+        self.sp = 0;
+        self.stack = [0; 16];
+        self.waiting_key = None;
+        self.waiting_vblank = false;
+        self.at_breakpoint = false;
+        self.watch_hit = None;
+        self.cycles = 0;
+        self.frame_cycles = 0;
+        self.history.clear();
+        self.last_tick = Instant::now();
+        self.update_sound();
+        if self.audio_pattern.take().is_some() {
+            self.sound.clear_pattern();
+        }
+        self.pitch = DEFAULT_PITCH;
 
-        let ins = Instruction::from(ins);
+        screen.set_hires(false);
+        screen.select_planes(1);
+        screen.clear();
+    }
 
-        match ins {
-            Instruction::Sys(nnn) => {
-                self.pc = nnn as usize;
+    /// Copies `rom` into memory at the program counter, failing if it doesn't fit.
+    pub fn load(&mut self, rom: &[u8]) -> io::Result<()> {
+        self.load_at(rom, self.pc)
+    }
+
+    /// Like `load`, but first applies the IPS `patch` to `rom`, see `ips::apply`. Fails without
+    /// touching memory if the patch is malformed.
+    pub fn load_patched(&mut self, rom: &[u8], patch: &[u8]) -> io::Result<()> {
+        self.load(&ips::apply(rom, patch)?)
+    }
+
+    /// Copies `rom` into memory at `addr` and starts running it from there, for platforms like
+    /// the ETI 660 whose programs start at 0x600. `reset` goes back to `addr` too. Fails if the
+    /// ROM doesn't fit above `addr`.
+    pub fn load_at(&mut self, rom: &[u8], addr: usize) -> io::Result<()> {
+        if addr >= self.ram.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{addr:#05x} is past the end of memory"),
+            ));
+        }
+        let available = self.ram.len() - addr;
+        if rom.len() > available {
+            return Err(rom_too_large(rom.len(), available));
+        }
+
+        let end = addr + rom.len();
+        self.ram[addr..end].copy_from_slice(rom);
+        self.invalidate_decoded(addr, rom.len());
+        self.rom = rom.to_vec();
+        self.entry_point = addr;
+        self.pc = addr;
+
+        Ok(())
+    }
+
+    /// Where the loaded program starts, 0x200 unless it was loaded with `load_at`.
+    pub fn entry_point(&self) -> usize {
+        self.entry_point
+    }
+
+    /// Like `load`, but first switches to the quirks `profiles` has for `rom`. ROMs that aren't
+    /// in the table keep the current quirks. Returns the quirks the ROM will run with.
+    pub fn load_with_profiles(&mut self, rom: &[u8], profiles: &Profiles) -> io::Result<Quirks> {
+        self.load(rom)?;
+        if let Some(quirks) = profiles.get(rom) {
+            self.quirks = quirks;
+        }
+
+        Ok(self.quirks)
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Loads one of the ROMs bundled with the library.
+    pub fn load_builtin(&mut self, rom: BuiltinRom) -> io::Result<()> {
+        self.load(rom.bytes())
+    }
+
+    /// Like `load`, but reads the ROM from `r`. At most one byte more than fits in memory is
+    /// read before giving up on an oversized ROM.
+    pub fn load_reader<R: Read>(&mut self, r: &mut R) -> io::Result<()> {
+        let available = self.ram.len().saturating_sub(self.pc);
+        let mut rom = Vec::new();
+        r.take(available as u64 + 1).read_to_end(&mut rom)?;
+
+        self.load(&rom)
+    }
+
+    /// The value of register Vx.
+    pub fn register(&self, x: usize) -> u8 {
+        self.registers[x]
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn i(&self) -> u16 {
+        self.register_i
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.register_delay
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.register_sound
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.register_delay = value;
+    }
+
+    /// Sets the sound timer, starting or stopping the beep to match.
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.register_sound = value;
+        self.update_sound();
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    /// The return addresses currently on the stack, oldest first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// The SUPER-CHIP user flags written by Fx75, where games keep high scores. They aren't
+    /// part of `MachineState`, so loading a state doesn't roll them back.
+    pub fn rpl_flags(&self) -> [u8; 8] {
+        self.rpl_flags
+    }
+
+    /// Restores flags saved from an earlier run.
+    pub fn set_rpl_flags(&mut self, flags: [u8; 8]) {
+        self.rpl_flags = flags;
+    }
+
+    /// Copies `len` bytes of memory from `start`, for inspecting it while debugging.
+    pub fn dump_memory(&self, start: usize, len: usize) -> Result<Vec<u8>, MachineError> {
+        let end = self.check_range(start, len)?;
+        Ok(self.ram[start..end].to_vec())
+    }
+
+    /// Overwrites memory at `addr` with `bytes`, to set up a scenario or apply a cheat.
+    /// Nothing is written if any of it falls outside memory. Watchpoints don't fire.
+    pub fn poke(&mut self, addr: usize, bytes: &[u8]) -> Result<(), MachineError> {
+        let end = self.check_range(addr, bytes.len())?;
+        self.ram[addr..end].copy_from_slice(bytes);
+        self.invalidate_decoded(addr, bytes.len());
+        Ok(())
+    }
+
+    /// The end of `len` bytes from `start`, or the first address past the end of memory.
+    fn check_range(&self, start: usize, len: usize) -> Result<usize, MachineError> {
+        match start.checked_add(len) {
+            Some(end) if end <= self.ram.len() => Ok(end),
+            _ => Err(MachineError::OutOfBounds {
+                addr: start.max(self.ram.len()),
+            }),
+        }
+    }
+
+    /// Switches to the XO-CHIP memory layout: 64KB of RAM, addressable through F000 nnnn.
+    ///
+    /// Classic ROMs keep the original 4KB. Memory past the new size is dropped when switching
+    /// back, along with the loaded ROM if it no longer fits, so `reset` has nothing to restart.
+    pub fn set_xo_chip(&mut self, enabled: bool) {
+        self.xo_chip = enabled;
+        self.resize_ram(if enabled { XO_RAM_SIZE } else { RAM_SIZE });
+    }
+
+    /// Resizes RAM, forgetting the loaded ROM if it no longer fits so `reset` can't copy it
+    /// past the end.
+    fn resize_ram(&mut self, size: usize) {
+        self.ram.resize(size);
+        if self.entry_point + self.rom.len() > size {
+            self.rom.clear();
+            self.entry_point = PROGRAM_START_AT;
+        }
+        self.set_decode_cache(self.decode_cache.is_some());
+    }
+
+    /// Makes jumps, calls and returns to odd addresses fault with `MisalignedJump` instead of
+    /// going ahead. Off by default, a few ROMs run misaligned code on purpose, but a stray odd
+    /// target usually means every fetch after it decodes garbage.
+    pub fn set_strict_alignment(&mut self, enabled: bool) {
+        self.strict_alignment = enabled;
+    }
+
+    /// Makes Fx33 and Fx55 fault with `WriteProtected` instead of storing anything when they
+    /// would write into `range`, to catch programs scribbling over memory they shouldn't.
+    /// Loading ROMs and `poke` aren't affected.
+    pub fn protect(&mut self, range: Range<usize>) {
+        self.protected.push(range);
+    }
+
+    /// Write protects the fonts, which no well behaved program changes.
+    pub fn protect_font(&mut self) {
+        self.protect(FONT_AREA);
+    }
+
+    /// Lifts all write protection.
+    pub fn clear_protection(&mut self) {
+        self.protected.clear();
+    }
+
+    /// Makes fetches and the memory accesses of Dxyn, Fx33, Fx55, Fx65 and F002 wrap around
+    /// to address 0 past the end of RAM, like some hardware does, instead of faulting with
+    /// `OutOfBounds`. Off by default, running off the end is almost always a bug.
+    pub fn set_wrap_memory(&mut self, enabled: bool) {
+        self.wrap_memory = enabled;
+    }
+
+    /// Starts or stops counting how many times each kind of instruction runs, see
+    /// `opcode_counts`. Off by default, it costs a hash map update per instruction.
+    pub fn set_opcode_counts(&mut self, enabled: bool) {
+        self.opcode_counts = enabled.then(HashMap::new);
+    }
+
+    /// How many times each kind of instruction ran since counting started, keyed by
+    /// `Instruction::pattern`. Empty while counting is off.
+    pub fn opcode_counts(&self) -> HashMap<&'static str, u64> {
+        self.opcode_counts.clone().unwrap_or_default()
+    }
+
+    /// Remembers each instruction after decoding it the first time, so hot loops skip the
+    /// decoder. Writes to memory drop the affected entries, so self-modifying code still works.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = enabled.then(|| vec![None; self.ram.len()]);
+    }
+
+    pub fn is_xo_chip(&self) -> bool {
+        self.xo_chip
+    }
+
+    /// Reseeds the generator behind Cxkk, so the same seed always gives the same bytes.
+    ///
+    /// Machines start seeded from the OS entropy source.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// How many instructions have been executed so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Sets how many cycles each instruction costs, see `last_cost`.
+    pub fn set_cost_model(&mut self, model: CostModel) {
+        self.cost_model = model;
+    }
+
+    /// The collisions of the most recent Dxyn. VF only says whether there were any, this
+    /// has how many and in which planes, for tuning hitboxes.
+    pub fn last_draw_collisions(&self) -> DrawCollisions {
+        self.last_draw
+    }
+
+    /// The cycles the last `step` took under the cost model. A step that didn't execute an
+    /// instruction, because the machine was paused or waiting, still takes one cycle.
+    pub fn last_cost(&self) -> u32 {
+        self.last_cost
+    }
+
+    /// Replaces the backend that plays the beeper while the sound timer is running.
+    pub fn set_sound(&mut self, sound: Box<dyn Sound>) {
+        if self.beeping {
+            self.sound.stop();
+            self.beeping = false;
+        }
+        self.sound = sound;
+        if let Some(pattern) = &self.audio_pattern {
+            self.sound.set_pattern(pattern, self.pitch);
+        }
+    }
+
+    /// The XO-CHIP audio pattern loaded by F002, `None` until one is loaded.
+    pub fn audio_pattern(&self) -> Option<[u8; PATTERN_BYTES]> {
+        self.audio_pattern
+    }
+
+    /// The XO-CHIP pitch register set by Fx3A.
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Installs a hook that sees every instruction before it executes, for debugging.
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.trace_hook = Some(hook);
+    }
+
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
+
+    /// Installs a hook told whenever the tone starts and stops, for visualisers and the like
+    /// that want to follow the sound timer without polling it.
+    pub fn set_sound_hook(&mut self, hook: SoundHook) {
+        self.sound_hook = Some(hook);
+    }
+
+    pub fn clear_sound_hook(&mut self) {
+        self.sound_hook = None;
+    }
+
+    /// Sets how many instructions per second the host should execute.
+    ///
+    /// This only changes the pacing reported by `step_interval`, the delay and sound timers
+    /// keep ticking at 60 Hz whatever the clock is.
+    pub fn set_clock_hz(&mut self, hz: u32) {
+        self.step_interval = clock_interval(hz.max(1));
+    }
+
+    /// Chooses what counts the delay and sound timers down, see `TimingMode`. Switching starts
+    /// a fresh frame, and going back to real time carries on from the current time.
+    pub fn set_timing_mode(&mut self, mode: TimingMode) {
+        self.timing_mode = mode;
+        self.frame_cycles = 0;
+        self.last_tick = Instant::now();
+    }
+
+    /// Makes the delay and sound timers tick `speed` times faster than 60 Hz, for fast-forwarding.
+    pub fn set_timer_speed(&mut self, speed: u32) {
+        self.timer_speed = speed.max(1);
+    }
+
+    /// Wall-clock time of one cycle at the configured clock, the time between two `step` calls
+    /// when every instruction costs one cycle.
+    pub fn step_interval(&self) -> std::time::Duration {
+        self.step_interval
+    }
+
+    pub fn save_state(&self, screen: &Screen) -> MachineState {
+        MachineState {
+            ram: self.ram.to_vec(),
+            registers: self.registers,
+            register_i: self.register_i,
+            register_delay: self.register_delay,
+            register_sound: self.register_sound,
+            pc: self.pc,
+            sp: self.sp,
+            stack: self.stack,
+            screen: screen.snapshot(),
+            screen_size: (screen.width(), screen.height()),
+            selected_planes: screen.selected_planes(),
+        }
+    }
+
+    /// Restores a state from `save_state`. States may come from disk, so one that couldn't have
+    /// been saved is refused with `InvalidState`, leaving the machine and screen untouched.
+    pub fn load_state(
+        &mut self,
+        state: MachineState,
+        screen: &mut Screen,
+    ) -> Result<(), MachineError> {
+        let invalid = |reason| Err(MachineError::InvalidState { reason });
+        if state.ram.len() != RAM_SIZE && state.ram.len() != XO_RAM_SIZE {
+            return invalid("RAM is neither 4KB nor 64KB");
+        }
+        if state.pc >= state.ram.len() {
+            return invalid("PC past the end of memory");
+        }
+        if state.sp > state.stack.len() {
+            return invalid("stack pointer past the end of the stack");
+        }
+        let (width, height) = state.screen_size;
+        if width == 0 || height == 0 || state.screen.len() != width * height {
+            return invalid("screen size doesn't match its pixels");
+        }
+
+        self.xo_chip = state.ram.len() == XO_RAM_SIZE;
+        self.resize_ram(state.ram.len());
+        self.ram.copy_from_slice(&state.ram);
+        self.registers = state.registers;
+        self.register_i = state.register_i;
+        self.register_delay = state.register_delay;
+        self.register_sound = state.register_sound;
+        self.pc = state.pc;
+        self.sp = state.sp;
+        self.stack = state.stack;
+        // Waits and breakpoint stops belong to the run the state replaces.
+        self.waiting_key = None;
+        self.waiting_vblank = false;
+        self.at_breakpoint = false;
+        self.last_tick = Instant::now();
+        self.update_sound();
+
+        screen.set_resolution(width, height);
+        screen.restore(&state.screen);
+        screen.select_planes(state.selected_planes);
+
+        Ok(())
+    }
+
+    /// Reports a `StepOutcome::WatchpointHit` after any instruction that reads or writes `addr`
+    /// as data, depending on `kind`. Instruction fetches don't count.
+    pub fn add_watchpoint(&mut self, addr: usize, kind: WatchKind) {
+        self.watchpoints.insert(addr, kind);
+    }
+
+    /// Returns whether there was a watchpoint at `addr`.
+    pub fn remove_watchpoint(&mut self, addr: usize) -> bool {
+        self.watchpoints.remove(&addr).is_some()
+    }
+
+    /// Keeps up to `frames` states for `rewind`, the oldest are dropped first. 0, the default,
+    /// turns rewinding off.
+    pub fn set_rewind_limit(&mut self, frames: usize) {
+        self.history_limit = frames;
+        while self.history.len() > frames {
+            self.history.pop_front();
+        }
+    }
+
+    /// Remembers the current state for `rewind`, meant to be called once per frame.
+    pub fn record_history(&mut self, screen: &Screen) {
+        if self.history_limit == 0 {
+            return;
+        }
+        if self.history.len() == self.history_limit {
+            self.history.pop_front();
+        }
+        let state = self.save_state(screen);
+        self.history.push_back(state);
+    }
+
+    /// Goes back to the last state passed to `record_history` and forgets it, so repeated calls
+    /// keep going back in time. Returns false once there's nothing left to go back to.
+    pub fn rewind(&mut self, screen: &mut Screen) -> bool {
+        match self.history.pop_back() {
+            // Everything in the history came from `save_state`, so it always loads.
+            Some(state) => self.load_state(state, screen).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Pauses execution whenever the PC reaches `addr`, before the instruction there runs.
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Returns whether there was a breakpoint at `addr`.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    /// Runs `steps` instructions back to back, without any pacing, stopping at the first fault,
+    /// breakpoint or halt.
+    ///
+    /// Meant for tests and tools that drive the machine without a window.
+    pub fn run_headless(
+        &mut self,
+        steps: usize,
+        keyboard: &Keyboard,
+        screen: &mut Screen,
+    ) -> Result<StepOutcome, MachineError> {
+        let outcome = self.run_until(steps, keyboard, screen, |_| false)?;
+        Ok(outcome.unwrap_or(StepOutcome::Ok))
+    }
+
+    /// Like `run_headless`, but also stops as soon as `done` returns true, which is checked
+    /// before every step.
+    ///
+    /// Returns `None` if all `max_steps` ran without stopping, `Some(StepOutcome::Ok)` when
+    /// `done` stopped the run and the outcome that stopped it otherwise.
+    pub fn run_until(
+        &mut self,
+        max_steps: usize,
+        keyboard: &Keyboard,
+        screen: &mut Screen,
+        mut done: impl FnMut(&Self) -> bool,
+    ) -> Result<Option<StepOutcome>, MachineError> {
+        for _ in 0..max_steps {
+            if done(self) {
+                return Ok(Some(StepOutcome::Ok));
+            }
+            match self.step(keyboard, screen)? {
+                StepOutcome::Ok => {}
+                outcome => return Ok(Some(outcome)),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Decodes `count` consecutive instructions starting at `start`, pairing each with its
+    /// address.
+    ///
+    /// Words that aren't valid instructions are listed as `.db 0xNNNN`.
+    pub fn disassemble(&self, start: usize, count: usize) -> Vec<(usize, String)> {
+        let mut listing = Vec::new();
+        let mut addr = start;
+        while listing.len() < count && addr + 1 < self.ram.len() {
+            let word = self.word_at(addr);
+            let decoded = if self.xo_chip && word == 0xf000 && addr + 3 < self.ram.len() {
+                Instruction::decode(word, self.word_at(addr + 2))
+            } else {
+                Instruction::try_from(word)
+            };
+
+            match decoded {
+                Ok(ins) => {
+                    listing.push((addr, ins.to_string()));
+                    addr += ins.size();
+                }
+                Err(_) => {
+                    listing.push((addr, format!(".db 0x{word:04X}")));
+                    addr += 2;
+                }
+            }
+        }
+
+        listing
+    }
+
+    /// Follows the control flow of the loaded program from the entry point, see `load_at`, to
+    /// tell code from data, see `Analysis`.
+    pub fn analyze(&self) -> Analysis {
+        analyze(&self.ram, self.entry_point, self.xo_chip)
+    }
+
+    /// Brings the delay and sound timers up to date. `step` does this too, calling it from the
+    /// host's loop as well keeps the timers at 60 Hz when the CPU runs slowly or not at all,
+    /// like while waiting on a key.
+    pub fn update_timers(&mut self) {
+        if self.paused {
+            return;
+        }
+        self.tick_timers();
+        self.update_sound();
+    }
+
+    /// Counts both timers down by one and ends a `display_wait`, as one 60 Hz tick would. Does
+    /// nothing while paused.
+    pub fn tick(&mut self) {
+        if self.paused {
+            return;
+        }
+        self.register_delay = self.register_delay.saturating_sub(1);
+        self.register_sound = self.register_sound.saturating_sub(1);
+        self.waiting_vblank = false;
+        self.update_sound();
+    }
+
+    /// Freezes the machine: `step` does nothing and the timers stop counting down.
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.update_sound();
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.last_tick = Instant::now();
+        self.update_sound();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn step(
+        &mut self,
+        keyboard: &Keyboard,
+        screen: &mut Screen,
+    ) -> Result<StepOutcome, MachineError> {
+        if self.paused {
+            self.last_cost = 1;
+            return Ok(StepOutcome::Paused);
+        }
+
+        self.step_once(keyboard, screen)
+    }
+
+    /// Runs exactly one instruction, even while paused. Timers only advance when the machine
+    /// isn't paused.
+    pub fn step_once(
+        &mut self,
+        keyboard: &Keyboard,
+        screen: &mut Screen,
+    ) -> Result<StepOutcome, MachineError> {
+        if self.wrap_memory {
+            self.pc %= self.ram.len();
+        }
+        let pc = self.pc;
+        self.last_cost = 1;
+
+        if !self.at_breakpoint && self.breakpoints.contains(&pc) {
+            self.at_breakpoint = true;
+            return Ok(StepOutcome::BreakpointHit(pc));
+        }
+        self.at_breakpoint = false;
+
+        // With the display_wait quirk a draw holds the CPU until the next 60 Hz tick, like the
+        // original interpreter waiting for the vertical blank.
+        if self.waiting_vblank {
+            if !self.paused {
+                self.step_timers();
+            }
+            if self.waiting_vblank {
+                return Ok(StepOutcome::Ok);
+            }
+        }
+
+        if pc + 1 >= self.ram.len() && !self.wrap_memory {
+            return Err(MachineError::OutOfBounds { addr: pc + 1 });
+        }
+        let opcode = self.fetch(pc);
+
+        let cached = self.decode_cache.as_ref().and_then(|cache| cache[pc]);
+        let decoded = if let Some(ins) = cached {
+            Ok(ins)
+        } else if self.xo_chip && opcode == 0xf000 {
+            if pc + 3 >= self.ram.len() && !self.wrap_memory {
+                return Err(MachineError::OutOfBounds { addr: pc + 3 });
+            }
+            Instruction::decode(opcode, self.fetch((pc + 2) % self.ram.len()))
+        } else {
+            Instruction::try_from(opcode)
+        };
+        if let (Some(cache), Ok(ins), None) = (&mut self.decode_cache, decoded, cached) {
+            cache[pc] = Some(ins);
+        }
+
+        let ins = match decoded {
+            Ok(ins) => ins,
+            Err(e) => {
+                self.pc += 2;
+                return Err(e.at(pc).into());
+            }
+        };
+
+        if let Some(hook) = &mut self.trace_hook {
+            hook(pc, opcode, &ins);
+        }
+        if let Some(counts) = &mut self.opcode_counts {
+            *counts.entry(ins.pattern()).or_insert(0) += 1;
+        }
+
+        self.execute(ins, keyboard, screen)
+    }
+
+    /// Single steps like `step_once`, for symmetry with `step_over`.
+    pub fn step_into(
+        &mut self,
+        keyboard: &Keyboard,
+        screen: &mut Screen,
+    ) -> Result<StepOutcome, MachineError> {
+        self.step_once(keyboard, screen)
+    }
+
+    /// Runs one instruction, and when it's a call runs the whole subroutine too, stopping once
+    /// the stack is back to its depth from before. Like `step_once` it runs while paused.
+    ///
+    /// Returns `None` if `max_steps` ran out inside the call, and the outcome of the last step
+    /// otherwise. A breakpoint, watchpoint or halt inside the subroutine stops it early.
+    pub fn step_over(
+        &mut self,
+        max_steps: usize,
+        keyboard: &Keyboard,
+        screen: &mut Screen,
+    ) -> Result<Option<StepOutcome>, MachineError> {
+        let depth = self.sp;
+        for _ in 0..max_steps {
+            let outcome = self.step_once(keyboard, screen)?;
+            if outcome != StepOutcome::Ok || self.sp <= depth {
+                return Ok(Some(outcome));
             }
+        }
+
+        Ok(None)
+    }
+
+    /// Runs `ins` as if it had just been fetched from the PC: the PC moves past it, then the
+    /// instruction takes effect and the timers advance, like `step` without the fetch and
+    /// decode. Breakpoints, `display_wait` holds, the trace hook and the opcode counts only
+    /// apply to `step`.
+    pub fn execute(
+        &mut self,
+        ins: Instruction,
+        keyboard: &Keyboard,
+        screen: &mut Screen,
+    ) -> Result<StepOutcome, MachineError> {
+        let pc = self.pc;
+        self.pc += ins.size();
+        self.last_cost = 1;
+        let mut halted = false;
+
+        match ins {
+            // There's no machine code to call into, so like most interpreters ignore it. It still
+            // shows up in the trace hook for anyone chasing stray 0nnn words.
+            Instruction::Sys(_) => {}
             Instruction::Cls => {
                 screen.clear();
             }
+            Instruction::ScrollDown(n) => screen.scroll_down(n as usize),
+            Instruction::ScrollRight => screen.scroll_right(),
+            Instruction::ScrollLeft => screen.scroll_left(),
+            Instruction::LowRes => screen.set_hires(false),
+            Instruction::HighRes => screen.set_hires(true),
             Instruction::Ret => {
-                self.pc = self.stack[self.sp] as usize;
+                if self.sp == 0 {
+                    return Err(MachineError::StackUnderflow { pc });
+                }
+                self.check_alignment(pc, self.stack[self.sp - 1] as usize)?;
                 self.sp -= 1;
+                self.pc = self.stack[self.sp] as usize;
             }
             Instruction::Jmp(nnn) => {
+                self.check_alignment(pc, nnn as usize)?;
                 self.pc = nnn as usize;
+                halted = self.pc == pc;
             }
             Instruction::Call(nnn) => {
-                self.sp += 1;
+                if self.sp == self.stack.len() {
+                    return Err(MachineError::StackOverflow { pc });
+                }
+                self.check_alignment(pc, nnn as usize)?;
                 self.stack[self.sp] = self.pc as u16;
+                self.sp += 1;
                 self.pc = nnn as usize;
             }
             Instruction::SkipEq(x, kk) => {
                 if self.registers[x as usize] == kk {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
             Instruction::SkipNEq(x, kk) => {
                 if self.registers[x as usize] != kk {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
             Instruction::SkipEqV(x, y) => {
                 if self.registers[x as usize] == self.registers[y as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
             Instruction::Set(x, kk) => {
@@ -101,178 +1086,425 @@ impl Machine {
             Instruction::Load(x, y) => {
                 self.registers[x as usize] = self.registers[y as usize];
             }
-            Instruction::Or(x, y) => self.registers[x as usize] |= self.registers[y as usize],
-            Instruction::And(x, y) => self.registers[x as usize] &= self.registers[y as usize],
-            Instruction::Xor(x, y) => self.registers[x as usize] ^= self.registers[y as usize],
-            Instruction::AddCarry(x, y) => {
-                let mut extended_x = self.registers[x as usize] as usize;
-
-                extended_x += self.registers[y as usize] as usize;
-
-                self.registers[0xf] = if extended_x > 0xff { 1 } else { 0 };
-
-                self.registers[x as usize] = extended_x as u8;
+            Instruction::Or(x, y) => {
+                self.registers[x as usize] |= self.registers[y as usize];
+                if self.quirks.vf_reset_on_logic {
+                    self.registers[0xf] = 0;
+                }
             }
-            Instruction::SubCarry(x, y) => {
-                if self.registers[x as usize] > self.registers[y as usize] {
-                    self.registers[0xf] = 1;
-                } else {
+            Instruction::And(x, y) => {
+                self.registers[x as usize] &= self.registers[y as usize];
+                if self.quirks.vf_reset_on_logic {
+                    self.registers[0xf] = 0;
+                }
+            }
+            Instruction::Xor(x, y) => {
+                self.registers[x as usize] ^= self.registers[y as usize];
+                if self.quirks.vf_reset_on_logic {
                     self.registers[0xf] = 0;
                 }
+            }
+            // For the arithmetic below the flag is computed first and written to VF last, so
+            // that when Vx is VF itself the flag wins over the result.
+            Instruction::AddCarry(x, y) => {
+                let (res, carry) =
+                    self.registers[x as usize].overflowing_add(self.registers[y as usize]);
 
-                // TODO: we will make it doesn't overflow just to pass the panic but this should work like this.
-                (self.registers[x as usize], _) =
-                    self.registers[x as usize].overflowing_sub(self.registers[y as usize]);
+                self.registers[x as usize] = res;
+                self.registers[0xf] = carry as u8;
             }
-            Instruction::Shr(x, _y) => {
-                // TODO: what to do with Y??
-                self.registers[0xf] = self.registers[x as usize] & 0x1;
-                self.registers[x as usize] /= 2;
+            Instruction::SubCarry(x, y) => {
+                let vx = self.registers[x as usize];
+                let vy = self.registers[y as usize];
+                let not_borrow = (vx > vy) as u8;
+
+                self.registers[x as usize] = vx.wrapping_sub(vy);
+                self.registers[0xf] = not_borrow;
+            }
+            Instruction::Shr(x, y) => {
+                let source = if self.quirks.shift_uses_vy { y } else { x };
+                let value = self.registers[source as usize];
+
+                self.registers[x as usize] = value >> 1;
+                self.registers[0xf] = value & 0x1;
             }
             Instruction::SubN(x, y) => {
-                if self.registers[y as usize] > self.registers[x as usize] {
-                    self.registers[0xf] = 1;
-                } else {
-                    self.registers[0xf] = 0;
-                }
+                let vx = self.registers[x as usize];
+                let vy = self.registers[y as usize];
+                let not_borrow = (vy > vx) as u8;
 
-                (self.registers[x as usize], _) = self.registers[y as usize].overflowing_sub(self.registers[x as usize]);
+                self.registers[x as usize] = vy.wrapping_sub(vx);
+                self.registers[0xf] = not_borrow;
             }
-            Instruction::Shl(x, _y) => {
-                // TODO: what to do with Y??
-                if (self.registers[x as usize] as usize) >> 0xf & 0x1 == 1 {
-                    self.registers[0xf] = 1;
-                } else {
-                    self.registers[0xf] = 0;
-                }
-                (self.registers[x as usize], _) = self.registers[x as usize].overflowing_mul(2);
+            Instruction::Shl(x, y) => {
+                let source = if self.quirks.shift_uses_vy { y } else { x };
+                let value = self.registers[source as usize];
+
+                self.registers[x as usize] = value << 1;
+                self.registers[0xf] = value >> 7;
             }
             Instruction::Sne(x, y) => {
                 if self.registers[x as usize] != self.registers[y as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
             Instruction::LoadI(nnn) => {
                 self.register_i = nnn;
             }
             Instruction::JmpV0(nnn) => {
-                self.pc = self.registers[0] as usize + nnn as usize;
+                let base = if self.quirks.jump_with_vx {
+                    self.registers[(nnn >> 8) as usize]
+                } else {
+                    self.registers[0]
+                };
+                let target = base as usize + nnn as usize;
+                self.check_alignment(pc, target)?;
+                self.pc = target;
             }
             Instruction::Rnd(x, kk) => {
-                self.registers[x as usize] = random_byte() & kk;
+                self.registers[x as usize] = self.rng.gen::<u8>() & kk;
             }
             Instruction::Drw(x, y, n) => {
-                let x = x as usize;
-                let y = y as usize;
-                let n = n as usize;
+                let vx = self.registers[x as usize] as usize % screen.width();
+                let vy = self.registers[y as usize] as usize % screen.height();
+                // SUPER-CHIP's Dxy0 draws 16 rows of two bytes in high resolution.
+                let (rows, row_bytes) = match n {
+                    0 if screen.is_hires() => (16, 2),
+                    n => (n as usize, 1),
+                };
+                let size = rows * row_bytes;
+
+                // Each selected plane takes the next `size` bytes of sprite data, in plane order.
+                let mut addr = self.register_i as usize;
+                let planes = screen.selected_planes().count_ones() as usize;
+                self.check_access(addr, size * planes)?;
 
                 self.registers[0xF] = 0;
+                screen.begin_sprite();
+                let mut collided_planes = 0;
+                for plane in 0..PLANES {
+                    if screen.selected_planes() & (1 << plane) == 0 {
+                        continue;
+                    }
 
-                let sprite =
-                    &self.ram[self.register_i as usize..(self.register_i as usize + n as usize)];
-                for (i, byte) in sprite.iter().enumerate() {
-                    let y = (self.registers[y] as usize + i) % 32;
-                    for bit in 0..8 {
-                        let x = (self.registers[x] as usize + bit) % 64;
+                    for i in 0..rows {
+                        let row = (0..row_bytes).fold(0u16, |row, b| {
+                            row << 8
+                                | self.read_ram((addr + i * row_bytes + b) % self.ram.len()) as u16
+                        });
+                        let row_width = row_bytes * 8;
+                        let y = vy + i;
+                        if y >= screen.height() && self.quirks.clip_sprites {
+                            break;
+                        }
+                        let y = y % screen.height();
+                        for bit in 0..row_width {
+                            let x = vx + bit;
+                            if x >= screen.width() && self.quirks.clip_sprites {
+                                break;
+                            }
+                            let x = x % screen.width();
 
-                        let pixel = (byte >> (7 - bit)) & 1;
+                            let lit = (row >> (row_width - 1 - bit)) & 1 == 1;
+                            let was_lit = screen.is_set_in_plane(plane, x, y);
 
-                        let old_pixel = screen.get(x, y);
-                        self.registers[0x0F] |= pixel & old_pixel;
-                        screen.set(x, y, old_pixel ^ pixel);
+                            if lit && was_lit {
+                                self.registers[0x0F] = 1;
+                                collided_planes |= 1 << plane;
+                                screen.record_collision(x, y);
+                            }
+                            screen.set_in_plane(plane, x, y, (lit ^ was_lit) as u8);
+                        }
                     }
+                    addr += size;
                 }
+
+                self.last_draw = DrawCollisions {
+                    pixels: screen.last_collisions().len(),
+                    planes: collided_planes,
+                };
+                self.waiting_vblank = self.quirks.display_wait;
             }
+            // Only the low nibble picks the key, like the digit in Fx29.
             Instruction::SkipPressed(x) => {
-                if keyboard.is_pressed(self.registers[x as usize] as usize) {
-                    self.pc += 2;
+                if keyboard.is_pressed((self.registers[x as usize] & 0xf) as usize) {
+                    self.skip();
                 }
             }
             Instruction::SkipNPressed(x) => {
-                if !keyboard.is_pressed(self.registers[x as usize] as usize) {
-                    self.pc += 2;
+                if !keyboard.is_pressed((self.registers[x as usize] & 0xf) as usize) {
+                    self.skip();
                 }
             }
             Instruction::LoadDT(x) => self.registers[x as usize] = self.register_delay,
             Instruction::LoadKeyPress(x) => {
-                if let Some(i) = keyboard.get_pressed() {
-                    self.registers[x as usize] = i;
-                } else {
-                    // We will assume this call never happened, we will rollback
-                    // the PC then return.
-                    self.pc -= 2;
+                // Like the original interpreter, wait for a key to go down and then come back
+                // up. Keys that were already held when we started waiting don't count.
+                match self.waiting_key {
+                    Some(key) if keyboard.just_released(key as usize) => {
+                        self.registers[x as usize] = key;
+                        self.waiting_key = None;
+                    }
+                    Some(_) => self.pc -= 2,
+                    None => {
+                        self.waiting_key = keyboard.get_just_pressed();
+                        // We will assume this call never happened, we will rollback
+                        // the PC then return.
+                        self.pc -= 2;
+                    }
                 }
             }
             Instruction::SetDT(x) => self.register_delay = self.registers[x as usize],
             Instruction::SetST(x) => self.register_sound = self.registers[x as usize],
-            Instruction::AddI(x) => self.register_i += self.registers[x as usize] as u16,
-            Instruction::LoadSprite(x) => {
-                if self.registers[x as usize] > 15 {
-                    panic!("Ooh!")
+            Instruction::AddI(x) => {
+                self.register_i = self
+                    .register_i
+                    .wrapping_add(self.registers[x as usize] as u16);
+                if self.quirks.add_i_sets_vf {
+                    self.registers[0xf] = (self.register_i > 0x0fff) as u8;
                 }
-                self.register_i = (self.registers[x as usize] * 5) as u16
+            }
+            Instruction::LoadSprite(x) => {
+                // Only the low nibble picks the digit, like on the original interpreter.
+                let digit = self.registers[x as usize] & 0xf;
+                self.register_i = (FONT_START + digit as usize * 5) as u16;
+            }
+            Instruction::LoadBigSprite(x) => {
+                let digit = self.registers[x as usize] & 0xf;
+                self.register_i = (BIG_FONT_START + digit as usize * 10) as u16;
             }
             Instruction::LoadBCD(x) => {
                 let mut x = self.registers[x as usize];
 
-                self.ram[self.register_i as usize] = x / 100;
+                let i = self.register_i as usize;
+                self.check_access(i, 3)?;
+                self.check_writable(i, 3)?;
+                self.write_ram(i, x / 100);
                 x %= 100;
-                self.ram[self.register_i as usize + 1] = x / 10;
+                self.write_ram(i + 1, x / 10);
                 x %= 10;
-                self.ram[self.register_i as usize + 2] = x;
+                self.write_ram(i + 2, x);
             }
             Instruction::LoadAllI(x) => {
+                self.check_access(self.register_i as usize, x as usize + 1)?;
+                self.check_writable(self.register_i as usize, x as usize + 1)?;
                 for i in 0..=(x as usize) {
-                    self.ram[self.register_i as usize + i] = self.registers[i]
+                    self.write_ram(self.register_i as usize + i, self.registers[i])
+                }
+                if self.quirks.load_store_increments_i {
+                    self.register_i = self.register_i.wrapping_add(x as u16 + 1);
                 }
             }
             Instruction::SetAllI(x) => {
+                self.check_access(self.register_i as usize, x as usize + 1)?;
                 for i in 0..=(x as usize) {
-                    self.registers[i] = self.ram[self.register_i as usize + i]
+                    self.registers[i] = self.read_ram(self.register_i as usize + i)
+                }
+                if self.quirks.load_store_increments_i {
+                    self.register_i = self.register_i.wrapping_add(x as u16 + 1);
+                }
+            }
+            Instruction::StoreFlags(x) => {
+                let n = (x as usize).min(7) + 1;
+                self.rpl_flags[..n].copy_from_slice(&self.registers[..n]);
+            }
+            Instruction::LoadFlags(x) => {
+                let n = (x as usize).min(7) + 1;
+                self.registers[..n].copy_from_slice(&self.rpl_flags[..n]);
+            }
+            Instruction::SelectPlanes(_) | Instruction::LoadAudio | Instruction::SetPitch(_)
+                if !self.xo_chip =>
+            {
+                return Err(MachineError::Decode(DecodeError {
+                    opcode: ins.encode(),
+                    pc: Some(pc),
+                }));
+            }
+            Instruction::SelectPlanes(n) => screen.select_planes(n),
+            Instruction::LoadAudio => {
+                self.check_access(self.register_i as usize, PATTERN_BYTES)?;
+                let mut pattern = [0; PATTERN_BYTES];
+                for (i, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read_ram(self.register_i as usize + i);
+                }
+                self.audio_pattern = Some(pattern);
+                self.sound.set_pattern(&pattern, self.pitch);
+            }
+            Instruction::SetPitch(x) => {
+                self.pitch = self.registers[x as usize];
+                if let Some(pattern) = &self.audio_pattern {
+                    self.sound.set_pattern(pattern, self.pitch);
                 }
             }
+            Instruction::LongLoadI(nnnn) => {
+                self.register_i = nnnn;
+            }
         };
 
-        if self.last_tick.elapsed() >= std::time::Duration::from_micros(TIMER_RATE) {
-            if self.register_delay > 0 {
-                self.register_delay -= 1
-            };
-            if self.register_sound > 0 {
-                self.register_sound -= 1
-            };
+        self.cycles += 1;
+        self.last_cost = self.cost_model.cost(&ins, screen.is_hires());
+        if !self.paused {
+            self.step_timers();
+        }
+        self.update_sound();
 
-            self.last_tick = std::time::Instant::now();
+        if let Some((addr, access)) = self.watch_hit.take() {
+            return Ok(StepOutcome::WatchpointHit(addr, access));
+        }
+        if halted {
+            return Ok(StepOutcome::Halted(pc));
         }
+        Ok(StepOutcome::Ok)
     }
-}
 
-fn random_byte() -> u8 {
-    random()
-}
+    /// Reads memory for an instruction. Addresses past the end wrap around to the start, so
+    /// `check_access` first unless wrapping is on.
+    fn read_ram(&mut self, addr: usize) -> u8 {
+        let addr = addr % self.ram.len();
+        self.watch(addr, WatchKind::Read);
+        self.ram.read(addr)
+    }
 
-#[cfg(test)]
-mod tests {
-    use crate::keyboard::Keyboard;
-    use crate::machine::Machine;
-    use crate::screen::Screen;
+    fn write_ram(&mut self, addr: usize, value: u8) {
+        let addr = addr % self.ram.len();
+        self.watch(addr, WatchKind::Write);
+        self.ram.write(addr, value);
+        self.invalidate_decoded(addr, 1);
+    }
 
-    #[test]
-    fn test_load_bcd() {
-        let mut screen = Screen::new();
-        let keyboard = Keyboard::new();
-        let mut machine = Machine::new();
+    /// Drops cached instructions overlapping `len` bytes from `addr`, including ones starting
+    /// up to three bytes before it, the length of F000 nnnn.
+    fn invalidate_decoded(&mut self, addr: usize, len: usize) {
+        if let Some(cache) = &mut self.decode_cache {
+            let end = (addr + len).min(cache.len());
+            for entry in &mut cache[addr.saturating_sub(3)..end] {
+                *entry = None;
+            }
+        }
+    }
+
+    fn watch(&mut self, addr: usize, access: WatchKind) {
+        if self.watch_hit.is_some() {
+            return;
+        }
+        if let Some(kind) = self.watchpoints.get(&addr) {
+            if kind.matches(access) {
+                self.watch_hit = Some((addr, access));
+            }
+        }
+    }
+
+    /// Checks an instruction's access to `len` bytes from `start`, which only faults when
+    /// addresses don't wrap.
+    fn check_access(&self, start: usize, len: usize) -> Result<(), MachineError> {
+        if !self.wrap_memory && len > 0 {
+            self.check_range(start, len)?;
+        }
+        Ok(())
+    }
+
+    /// Faults with the first write protected address among the `len` bytes from `start`.
+    fn check_writable(&self, start: usize, len: usize) -> Result<(), MachineError> {
+        for addr in (start..start + len).map(|addr| addr % self.ram.len()) {
+            if self.protected.iter().any(|range| range.contains(&addr)) {
+                return Err(MachineError::WriteProtected { addr });
+            }
+        }
+        Ok(())
+    }
+
+    fn word_at(&self, addr: usize) -> u16 {
+        u16::from_be_bytes([self.ram[addr], self.ram[addr + 1]])
+    }
+
+    /// Reads an instruction word through `Memory::read`, unlike `word_at`. Watchpoints don't
+    /// see fetches.
+    fn fetch(&mut self, addr: usize) -> u16 {
+        let next = (addr + 1) % self.ram.len();
+        u16::from_be_bytes([self.ram.read(addr), self.ram.read(next)])
+    }
+
+    fn check_alignment(&self, pc: usize, target: usize) -> Result<(), MachineError> {
+        if self.strict_alignment && target % 2 == 1 {
+            return Err(MachineError::MisalignedJump { pc, target });
+        }
+        Ok(())
+    }
+
+    /// Skips the next instruction, which in XO-CHIP mode may be the four byte F000 nnnn.
+    fn skip(&mut self) {
+        let len = self.ram.len();
+        let fits = self.wrap_memory || self.pc + 1 < len;
+        let long = self.xo_chip && fits && self.fetch(self.pc % len) == 0xf000;
+        self.pc += if long { 4 } else { 2 };
+    }
+
+    /// Advances the timers for a step that ran, or waited on the display, according to the
+    /// timing mode.
+    fn step_timers(&mut self) {
+        match self.timing_mode {
+            TimingMode::RealTime => self.tick_timers(),
+            TimingMode::Lockstep { cycles_per_frame } => {
+                self.frame_cycles += 1;
+                if self.frame_cycles >= cycles_per_frame.max(1) {
+                    self.frame_cycles = 0;
+                    self.tick();
+                }
+            }
+            TimingMode::Manual => {}
+        }
+    }
+
+    /// Counts the timers down once for every 60 Hz period since the last tick, however many
+    /// steps ran in between. Only in real time.
+    fn tick_timers(&mut self) {
+        if self.timing_mode != TimingMode::RealTime {
+            return;
+        }
+        let rate = std::time::Duration::from_micros(TIMER_RATE) / self.timer_speed;
+        let ticks = (self.last_tick.elapsed().as_nanos() / rate.as_nanos()) as u32;
+        if ticks == 0 {
+            return;
+        }
 
-        machine.load(&[0xf4, 0x33]);
-        machine.registers[4] = 235;
+        let n = ticks.min(u8::MAX as u32) as u8;
+        self.register_delay = self.register_delay.saturating_sub(n);
+        self.register_sound = self.register_sound.saturating_sub(n);
+        self.waiting_vblank = false;
+        // Advance by whole periods so the leftover counts towards the next tick.
+        self.last_tick += rate * ticks;
+    }
 
-        machine.step(&keyboard, &mut screen);
+    /// Starts or stops the beep to follow the sound timer. A paused machine is silent.
+    fn update_sound(&mut self) {
+        let beeping = self.register_sound > 0 && !self.paused;
+        if beeping == self.beeping {
+            return;
+        }
 
-        assert_eq!(machine.ram[machine.register_i as usize], 2);
-        assert_eq!(machine.ram[machine.register_i as usize + 1], 3);
-        assert_eq!(machine.ram[machine.register_i as usize + 2], 5);
+        if beeping {
+            self.sound.start();
+        } else {
+            self.sound.stop();
+        }
+        if let Some(hook) = &mut self.sound_hook {
+            hook(beeping);
+        }
+        self.beeping = beeping;
     }
 }
 
+fn rom_too_large(size: usize, available: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("ROM is {size} bytes but only {available} fit in memory"),
+    )
+}
+
+fn clock_interval(hz: u32) -> std::time::Duration {
+    std::time::Duration::from_nanos(1_000_000_000 / hz as u64)
+}
+
 const NUMBERS: [u8; 5 * 16] = [
     // 0
     0b11110000,
@@ -371,3 +1603,2477 @@ const NUMBERS: [u8; 5 * 16] = [
     0b10000000,
     0b10000000,
 ];
+
+/// The SUPER-CHIP 8x10 digits Fx30 points I at, with the XO-CHIP additions for A to F.
+const BIG_NUMBERS: [u8; 10 * 16] = [
+    // 0
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    // 1
+    0b00011000,
+    0b01111000,
+    0b01111000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b11111111,
+    0b11111111,
+    // 2
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111,
+    // 3
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b11111111,
+    0b11111111,
+    // 4
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b00000011,
+    0b00000011,
+    // 5
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b11111111,
+    0b11111111,
+    // 6
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    // 7
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b00000110,
+    0b00001100,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    // 8
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    // 9
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b11111111,
+    0b11111111,
+    // A
+    0b01111110,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    // B
+    0b11111100,
+    0b11111100,
+    0b11000011,
+    0b11000011,
+    0b11111100,
+    0b11111100,
+    0b11000011,
+    0b11000011,
+    0b11111100,
+    0b11111100,
+    // C
+    0b00111100,
+    0b11111111,
+    0b11000011,
+    0b11000000,
+    0b11000000,
+    0b11000000,
+    0b11000000,
+    0b11000011,
+    0b11111111,
+    0b00111100,
+    // D
+    0b11111100,
+    0b11111110,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11111110,
+    0b11111100,
+    // E
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111,
+    // F
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11000000,
+    0b11000000,
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::cost::CostModel;
+    use crate::instructions::DecodeError;
+    use crate::instructions::Instruction;
+    use crate::keyboard::Keyboard;
+    use crate::machine::{
+        DrawCollisions, Machine, MachineError, MachineState, StepOutcome, TimingMode, WatchKind,
+        BIG_FONT_START, FONT_AREA, FONT_START, TIMER_RATE,
+    };
+    use crate::memory::Memory;
+    use crate::profiles::Profiles;
+    use crate::quirks::Quirks;
+    use crate::roms::BuiltinRom;
+    use crate::screen::Screen;
+    use crate::sound::Sound;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Sets up a machine for an instruction level test, e.g.
+    /// `MachineBuilder::new().reg(4, 235).program(&[0xf4, 0x33]).run(1)`. Clone one to run the
+    /// same setup under different quirks.
+    #[derive(Clone)]
+    struct MachineBuilder {
+        quirks: Quirks,
+        program: Vec<u8>,
+        registers: Vec<(usize, u8)>,
+        i: Option<u16>,
+        memory: Vec<(usize, Vec<u8>)>,
+        seed: Option<u64>,
+    }
+
+    impl MachineBuilder {
+        fn new() -> Self {
+            MachineBuilder {
+                quirks: Quirks::default(),
+                program: Vec::new(),
+                registers: Vec::new(),
+                i: None,
+                memory: Vec::new(),
+                seed: None,
+            }
+        }
+
+        fn quirks(mut self, quirks: Quirks) -> Self {
+            self.quirks = quirks;
+            self
+        }
+
+        fn program(mut self, program: &[u8]) -> Self {
+            self.program = program.to_vec();
+            self
+        }
+
+        fn reg(mut self, x: usize, value: u8) -> Self {
+            self.registers.push((x, value));
+            self
+        }
+
+        /// Sets V0, V1 and so on to `values`.
+        fn regs(mut self, values: &[u8]) -> Self {
+            self.registers.extend(values.iter().copied().enumerate());
+            self
+        }
+
+        fn i(mut self, addr: u16) -> Self {
+            self.i = Some(addr);
+            self
+        }
+
+        /// Writes `bytes` at `addr` after the program is loaded.
+        fn mem(mut self, addr: usize, bytes: &[u8]) -> Self {
+            self.memory.push((addr, bytes.to_vec()));
+            self
+        }
+
+        fn seed(mut self, seed: u64) -> Self {
+            self.seed = Some(seed);
+            self
+        }
+
+        /// Loads the program at 0x200 and runs `steps` instructions, panicking on any error.
+        fn run(self, steps: usize) -> Machine {
+            self.run_with_screen(steps).0
+        }
+
+        fn run_with_screen(self, steps: usize) -> (Machine, Screen) {
+            let mut screen = Screen::new();
+            let keyboard = Keyboard::new();
+            let mut machine = Machine::new(self.quirks);
+
+            machine.load(&self.program).unwrap();
+            for (x, value) in self.registers {
+                machine.registers[x] = value;
+            }
+            if let Some(i) = self.i {
+                machine.register_i = i;
+            }
+            for (addr, bytes) in self.memory {
+                machine.poke(addr, &bytes).unwrap();
+            }
+            if let Some(seed) = self.seed {
+                machine.seed_rng(seed);
+            }
+            for _ in 0..steps {
+                machine.step(&keyboard, &mut screen).unwrap();
+            }
+
+            (machine, screen)
+        }
+    }
+
+    #[test]
+    fn test_load_bcd() {
+        let machine = MachineBuilder::new()
+            .reg(4, 235)
+            .program(&[0xf4, 0x33])
+            .run(1);
+
+        let i = machine.i() as usize;
+        assert_eq!(&machine.memory()[i..i + 3], &[2, 3, 5]);
+    }
+
+    #[test]
+    fn test_arithmetic_flags() {
+        // 8014 - ADD V0, V1
+        let m = MachineBuilder::new()
+            .reg(0, 200)
+            .reg(1, 100)
+            .program(&[0x80, 0x14])
+            .run(1);
+        assert_eq!((m.register(0), m.register(0xf)), (44, 1));
+
+        // 8015 - SUB V0, V1
+        let m = MachineBuilder::new()
+            .reg(0, 5)
+            .reg(1, 7)
+            .program(&[0x80, 0x15])
+            .run(1);
+        assert_eq!((m.register(0), m.register(0xf)), (254, 0));
+
+        // 8017 - SUBN V0, V1
+        let m = MachineBuilder::new()
+            .reg(0, 5)
+            .reg(1, 7)
+            .program(&[0x80, 0x17])
+            .run(1);
+        assert_eq!((m.register(0), m.register(0xf)), (2, 1));
+    }
+
+    #[test]
+    fn test_draw_from_memory() {
+        let clip = Quirks {
+            clip_sprites: true,
+            ..Quirks::default()
+        };
+        // D011 - DRW V0, V1, 1
+        let (m, screen) = MachineBuilder::new()
+            .quirks(clip)
+            .reg(0, 60)
+            .reg(1, 2)
+            .i(0x300)
+            .mem(0x300, &[0xff])
+            .program(&[0xd0, 0x11])
+            .run_with_screen(1);
+
+        assert!((60..64).all(|x| screen.is_set(x, 2)));
+        assert!(!screen.is_set(0, 2));
+        assert_eq!(m.register(0xf), 0);
+    }
+
+    #[test]
+    fn test_accessors() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 6A42 - LD VA, 0x42
+        // A123 - LD I, 0x123
+        // 2300 - CALL 0x300
+        machine.load(&[0x6a, 0x42, 0xa1, 0x23, 0x23, 0x00]).unwrap();
+        machine.run_headless(3, &keyboard, &mut screen).unwrap();
+
+        assert_eq!(machine.register(0xa), 0x42);
+        assert_eq!(machine.registers()[0xa], 0x42);
+        assert_eq!(machine.i(), 0x123);
+        assert_eq!(machine.pc(), 0x300);
+        assert_eq!(machine.sp(), 1);
+        assert_eq!(machine.stack(), &[0x206]);
+        assert_eq!(&machine.memory()[0x200..0x202], &[0x6a, 0x42]);
+    }
+
+    #[test]
+    fn test_step_unknown_opcode() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        machine.load(&[0x51, 0x21, 0x60, 0x07]).unwrap();
+
+        let err = machine.step(&keyboard, &mut screen).unwrap_err();
+        assert_eq!(
+            err,
+            MachineError::Decode(DecodeError {
+                opcode: 0x5121,
+                pc: Some(0x200)
+            })
+        );
+
+        // The bad word is skipped and execution carries on.
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[0], 7);
+    }
+
+    #[test]
+    fn test_timers_independent_of_clock() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+
+        for hz in [300, 600, 1200] {
+            let mut machine = Machine::new(Quirks::default());
+            machine.set_clock_hz(hz);
+            assert_eq!(
+                machine.step_interval().as_nanos(),
+                1_000_000_000 / hz as u128
+            );
+
+            // 1200 - JP 0x200, spin in place.
+            machine.load(&[0x12, 0x00]).unwrap();
+            machine.register_delay = 0xff;
+
+            // Pretend each step happens one clock period after the previous one, so one
+            // second of emulated time should tick the timer 60 times.
+            for _ in 0..hz {
+                machine.last_tick -= machine.step_interval();
+                machine.step(&keyboard, &mut screen).unwrap();
+            }
+
+            assert_eq!(machine.register_delay, 0xff - 60);
+        }
+    }
+
+    #[derive(Default)]
+    struct Events {
+        starts: usize,
+        stops: usize,
+        patterns: Vec<([u8; 16], u8)>,
+    }
+
+    struct RecordingSound(Rc<RefCell<Events>>);
+
+    impl Sound for RecordingSound {
+        fn start(&mut self) {
+            self.0.borrow_mut().starts += 1;
+        }
+
+        fn stop(&mut self) {
+            self.0.borrow_mut().stops += 1;
+        }
+
+        fn set_pattern(&mut self, pattern: &[u8; 16], pitch: u8) {
+            self.0.borrow_mut().patterns.push((*pattern, pitch));
+        }
+    }
+
+    #[test]
+    fn test_xo_chip_audio() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        let events = Rc::new(RefCell::new(Events::default()));
+        machine.set_sound(Box::new(RecordingSound(events.clone())));
+
+        // A300 - LD I, 0x300
+        // F002 - AUDIO
+        // 6070 - LD V0, 0x70
+        // F03A - PITCH V0
+        machine
+            .load(&[0xa3, 0x00, 0xf0, 0x02, 0x60, 0x70, 0xf0, 0x3a])
+            .unwrap();
+
+        // Only XO-CHIP has an audio buffer.
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert!(matches!(
+            machine.step(&keyboard, &mut screen),
+            Err(MachineError::Decode(_))
+        ));
+
+        machine.set_xo_chip(true);
+        machine.reset(&mut screen);
+        let pattern: [u8; 16] = std::array::from_fn(|i| i as u8);
+        machine.ram[0x300..0x310].copy_from_slice(&pattern);
+        machine.run_headless(4, &keyboard, &mut screen).unwrap();
+        assert_eq!(machine.audio_pattern(), Some(pattern));
+        assert_eq!(machine.pitch(), 0x70);
+        assert_eq!(events.borrow().patterns, [(pattern, 64), (pattern, 0x70)]);
+
+        machine.reset(&mut screen);
+        assert_eq!(machine.audio_pattern(), None);
+        assert_eq!(machine.pitch(), 64);
+    }
+
+    #[test]
+    fn test_sound_follows_sound_timer() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        let events = Rc::new(RefCell::new(Events::default()));
+        machine.set_sound(Box::new(RecordingSound(events.clone())));
+
+        // 6002 - LD V0, 2
+        // F018 - LD ST, V0
+        // 1204 - JP 0x204
+        machine.load(&[0x60, 0x02, 0xf0, 0x18, 0x12, 0x04]).unwrap();
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(events.borrow().starts, 1);
+        assert_eq!(events.borrow().stops, 0);
+
+        // Keep spinning without the timer moving, the tone mustn't restart.
+        machine.step(&keyboard, &mut screen).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(events.borrow().starts, 1);
+
+        for _ in 0..2 {
+            machine.last_tick -= std::time::Duration::from_micros(super::TIMER_RATE);
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+        assert_eq!(machine.register_sound, 0);
+        assert_eq!(events.borrow().starts, 1);
+        assert_eq!(events.borrow().stops, 1);
+    }
+
+    #[test]
+    fn test_quirk_shift_uses_vy() {
+        // 8126 - SHR V1, V2
+        // 834E - SHL V3, V4
+        let setup = MachineBuilder::new()
+            .program(&[0x81, 0x26, 0x83, 0x4e])
+            .regs(&[0, 0b0000_0100, 0b0000_0011, 0b0000_0001, 0b1100_0000]);
+
+        let m = setup.clone().run(1);
+        assert_eq!(m.registers[1], 0b0000_0010);
+        assert_eq!(m.registers[0xf], 0);
+        let m = setup.clone().run(2);
+        assert_eq!(m.registers[3], 0b0000_0010);
+        assert_eq!(m.registers[0xf], 0);
+
+        let setup = setup.quirks(Quirks {
+            shift_uses_vy: true,
+            ..Quirks::default()
+        });
+        let m = setup.clone().run(1);
+        assert_eq!(m.registers[1], 0b0000_0001);
+        assert_eq!(m.registers[0xf], 1);
+        let m = setup.run(2);
+        assert_eq!(m.registers[3], 0b1000_0000);
+        assert_eq!(m.registers[0xf], 1);
+    }
+
+    #[test]
+    fn test_quirk_load_store_increments_i() {
+        // A300 - LD I, 0x300
+        // F255 - LD [I], V2
+        // F165 - LD V1, [I]
+        let setup = MachineBuilder::new()
+            .program(&[0xa3, 0x00, 0xf2, 0x55, 0xf1, 0x65])
+            .regs(&[1, 2, 3]);
+
+        let m = setup.clone().run(3);
+        assert_eq!(m.register_i, 0x300);
+        assert_eq!(&m.ram[0x300..0x303], &[1, 2, 3]);
+        assert_eq!(&m.registers[..2], &[1, 2]);
+
+        let setup = setup.quirks(Quirks {
+            load_store_increments_i: true,
+            ..Quirks::default()
+        });
+        let m = setup.clone().run(2);
+        assert_eq!(m.register_i, 0x303);
+        assert_eq!(&m.ram[0x300..0x303], &[1, 2, 3]);
+        let m = setup.run(3);
+        assert_eq!(m.register_i, 0x305);
+        assert_eq!(&m.registers[..2], &[0, 0]);
+    }
+
+    #[test]
+    fn test_quirk_jump_with_vx() {
+        // B310 - JP V0, 0x310
+        let setup = MachineBuilder::new()
+            .program(&[0xb3, 0x10])
+            .reg(0, 0x02)
+            .reg(3, 0x20);
+
+        let m = setup.clone().run(1);
+        assert_eq!(m.pc, 0x312);
+
+        let m = setup
+            .quirks(Quirks {
+                jump_with_vx: true,
+                ..Quirks::default()
+            })
+            .run(1);
+        assert_eq!(m.pc, 0x330);
+    }
+
+    #[test]
+    fn test_quirk_vf_reset_on_logic() {
+        for n in [0x1, 0x2, 0x3] {
+            // 812n - OR/AND/XOR V1, V2
+            let setup = MachineBuilder::new().program(&[0x81, 0x20 | n]).reg(0xf, 1);
+
+            let m = setup.clone().run(1);
+            assert_eq!(m.registers[0xf], 1);
+
+            let m = setup
+                .quirks(Quirks {
+                    vf_reset_on_logic: true,
+                    ..Quirks::default()
+                })
+                .run(1);
+            assert_eq!(m.registers[0xf], 0);
+        }
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // Each word calls the next one: 2202, 2204, ..., 2222.
+        let program: Vec<u8> = (1..=17u16)
+            .flat_map(|i| (0x2200 + i * 2).to_be_bytes())
+            .collect();
+        machine.load(&program).unwrap();
+
+        for depth in 1..=16 {
+            machine.step(&keyboard, &mut screen).unwrap();
+            assert_eq!(machine.sp, depth);
+            assert_eq!(machine.stack[depth - 1], 0x200 + depth as u16 * 2);
+        }
+        assert_eq!(machine.pc, 0x220);
+
+        let err = machine.step(&keyboard, &mut screen).unwrap_err();
+        assert_eq!(err, MachineError::StackOverflow { pc: 0x220 });
+        assert_eq!(machine.sp, 16);
+    }
+
+    #[test]
+    fn test_call_and_return() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 2300 - CALL 0x300
+        machine.load(&[0x23, 0x00]).unwrap();
+        // 00EE - RET
+        machine.ram[0x300..0x302].copy_from_slice(&[0x00, 0xee]);
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!((machine.pc, machine.sp), (0x300, 1));
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!((machine.pc, machine.sp), (0x202, 0));
+    }
+
+    #[test]
+    fn test_return_with_empty_stack() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        machine.load(&[0x00, 0xee]).unwrap();
+
+        let err = machine.step(&keyboard, &mut screen).unwrap_err();
+        assert_eq!(err, MachineError::StackUnderflow { pc: 0x200 });
+        assert_eq!((machine.pc, machine.sp), (0x202, 0));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut machine = Machine::new(Quirks::default());
+
+        machine
+            .load(&[0x00, 0xe0, 0x63, 0x1f, 0x51, 0x21, 0x12, 0x00])
+            .unwrap();
+
+        assert_eq!(
+            machine.disassemble(0x200, 4),
+            vec![
+                (0x200, "CLS".to_string()),
+                (0x202, "LD V3, 0x1F".to_string()),
+                (0x204, ".db 0x5121".to_string()),
+                (0x206, "JP 0x200".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 7001 - ADD V0, 1
+        // 4010 - SNE V0, 0x10
+        // 6000 - LD V0, 0
+        // F029 - LD F, V0
+        // D125 - DRW V1, V2, 5
+        // 7103 - ADD V1, 3
+        // 1200 - JP 0x200
+        machine
+            .load(&[
+                0x70, 0x01, 0x40, 0x10, 0x60, 0x00, 0xf0, 0x29, 0xd1, 0x25, 0x71, 0x03, 0x12, 0x00,
+            ])
+            .unwrap();
+
+        for _ in 0..20 {
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+        let saved = machine.save_state(&screen);
+
+        for _ in 0..30 {
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+        let expected = machine.save_state(&screen);
+        assert_ne!(saved, expected);
+
+        machine.load_state(saved.clone(), &mut screen).unwrap();
+        assert_eq!(machine.save_state(&screen), saved);
+
+        for _ in 0..30 {
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+        assert_eq!(machine.save_state(&screen), expected);
+    }
+
+    #[test]
+    fn test_load_state_clears_waits() {
+        let mut screen = Screen::new();
+        let mut keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 6105 - LD V1, 5
+        // F00A - LD V0, K
+        machine.load(&[0x61, 0x05, 0xf0, 0x0a]).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+        let saved = machine.save_state(&screen);
+
+        machine.add_breakpoint(0x202);
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Ok(StepOutcome::BreakpointHit(0x202))
+        );
+        // Start waiting on key 5, then stop on the breakpoint again.
+        keyboard.press(5);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.waiting_key, Some(5));
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Ok(StepOutcome::BreakpointHit(0x202))
+        );
+
+        machine.load_state(saved, &mut screen).unwrap();
+        assert_eq!(machine.waiting_key, None);
+        assert!(!machine.waiting_vblank);
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Ok(StepOutcome::BreakpointHit(0x202))
+        );
+    }
+
+    #[test]
+    fn test_load_invalid_state() {
+        let mut screen = Screen::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.load(&[0x00, 0xe0]).unwrap();
+        let saved = machine.save_state(&screen);
+
+        let corruptions: [fn(&mut MachineState); 5] = [
+            |s| s.sp = 17,
+            |s| s.pc = 0x1000,
+            |s| s.ram.truncate(100),
+            |s| s.ram.resize(0x2000, 0),
+            |s| s.screen_size = (128, 64),
+        ];
+        for corrupt in corruptions {
+            let mut state = saved.clone();
+            corrupt(&mut state);
+            assert!(matches!(
+                machine.load_state(state, &mut screen),
+                Err(MachineError::InvalidState { .. })
+            ));
+            assert_eq!(machine.save_state(&screen), saved);
+        }
+
+        // A full stack is fine.
+        let mut state = saved.clone();
+        state.sp = 16;
+        machine.load_state(state, &mut screen).unwrap();
+        assert_eq!(machine.sp(), 16);
+        assert_eq!(machine.stack().len(), 16);
+    }
+
+    #[test]
+    fn test_hires_draw_wraps_at_128x64() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 00FF - HIGH
+        // D011 - DRW V0, V1, 1
+        // 00FE - LOW
+        machine.load(&[0x00, 0xff, 0xd0, 0x11, 0x00, 0xfe]).unwrap();
+        machine.registers[0] = 124;
+        machine.registers[1] = 63;
+        machine.register_i = 0x300;
+        machine.ram[0x300] = 0xff;
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!((screen.width(), screen.height()), (128, 64));
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert!(screen.is_set(127, 63));
+        assert!(screen.is_set(0, 63));
+        assert!(screen.is_set(3, 63));
+        assert!(!screen.is_set(4, 63));
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!((screen.width(), screen.height()), (64, 32));
+    }
+
+    #[test]
+    fn test_draw_16x16_sprite() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks {
+            display_wait: false,
+            ..Quirks::default()
+        });
+
+        // D010 - DRW V0, V1, 0
+        // 00FF - HIGH
+        // D010 - DRW V0, V1, 0
+        // D010 - DRW V0, V1, 0
+        machine
+            .load(&[0xd0, 0x10, 0x00, 0xff, 0xd0, 0x10, 0xd0, 0x10])
+            .unwrap();
+        machine.registers[0] = 10;
+        machine.registers[1] = 5;
+        machine.register_i = 0x300;
+        // A hollow square: full top and bottom rows, the outer columns in between.
+        machine.ram[0x300..0x302].copy_from_slice(&[0xff, 0xff]);
+        for row in 1..15 {
+            machine.ram[0x300 + row * 2..0x302 + row * 2].copy_from_slice(&[0x80, 0x01]);
+        }
+        machine.ram[0x31e..0x320].copy_from_slice(&[0xff, 0xff]);
+
+        // In low resolution Dxy0 has no rows to draw.
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(screen.set_pixels().count(), 0);
+
+        machine.run_headless(2, &keyboard, &mut screen).unwrap();
+        let mut lit: Vec<_> = screen.set_pixels().map(|(x, y, _)| (x, y)).collect();
+        lit.sort();
+        let mut expected: Vec<_> = (10..26)
+            .flat_map(|x| (5..21).map(move |y| (x, y)))
+            .filter(|&(x, y)| x == 10 || x == 25 || y == 5 || y == 20)
+            .collect();
+        expected.sort();
+        assert_eq!(lit, expected);
+        assert_eq!(machine.registers[0xf], 0);
+
+        // Drawing it again erases all 60 pixels.
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert!(expected.iter().all(|&(x, y)| !screen.is_set(x, y)));
+        assert_eq!(machine.registers[0xf], 1);
+        assert_eq!(screen.last_collisions().len(), 60);
+    }
+
+    fn draw_on_right_edge(quirks: Quirks) -> Screen {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(quirks);
+
+        // D011 - DRW V0, V1, 1
+        machine.load(&[0xd0, 0x11]).unwrap();
+        machine.registers[0] = 60;
+        machine.registers[1] = 31;
+        machine.register_i = 0x300;
+        machine.ram[0x300] = 0xff;
+        machine.step(&keyboard, &mut screen).unwrap();
+        screen
+    }
+
+    #[test]
+    fn test_draw_wraps_past_right_edge() {
+        let screen = draw_on_right_edge(Quirks::default());
+        assert!(screen.is_set(60, 31));
+        assert!(screen.is_set(63, 31));
+        assert!(screen.is_set(0, 31));
+        assert!(screen.is_set(3, 31));
+        assert!(!screen.is_set(4, 31));
+    }
+
+    #[test]
+    fn test_draw_clips_past_right_edge() {
+        let quirks = Quirks {
+            clip_sprites: true,
+            ..Quirks::default()
+        };
+        let screen = draw_on_right_edge(quirks);
+        assert!(screen.is_set(60, 31));
+        assert!(screen.is_set(63, 31));
+        assert!((0..4).all(|x| !screen.is_set(x, 31)));
+    }
+
+    #[test]
+    fn test_clipped_draw_still_wraps_start_position() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks {
+            clip_sprites: true,
+            ..Quirks::default()
+        });
+
+        // D012 - DRW V0, V1, 2
+        machine.load(&[0xd0, 0x12]).unwrap();
+        machine.registers[0] = 66;
+        machine.registers[1] = 31;
+        machine.register_i = 0x300;
+        machine.ram[0x300] = 0x80;
+        machine.ram[0x301] = 0x80;
+        machine.step(&keyboard, &mut screen).unwrap();
+
+        assert!(screen.is_set(2, 31));
+        assert!(!screen.is_set(2, 0));
+    }
+
+    /// Draws a three row sprite whose last two rows fall past the bottom edge, in low or high
+    /// resolution.
+    fn draw_on_bottom_edge(clip_sprites: bool, hires: bool) -> Screen {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks {
+            clip_sprites,
+            ..Quirks::default()
+        });
+
+        // 00FF - HIGH, only in high resolution
+        // D013 - DRW V0, V1, 3
+        let program: &[u8] = if hires {
+            &[0x00, 0xff, 0xd0, 0x13]
+        } else {
+            &[0xd0, 0x13]
+        };
+        machine.load(program).unwrap();
+        machine.registers[0] = 8;
+        machine.registers[1] = if hires { 63 } else { 31 };
+        machine.register_i = 0x300;
+        machine.ram[0x300..0x303].copy_from_slice(&[0x80, 0x40, 0x20]);
+        machine
+            .run_headless(program.len() / 2, &keyboard, &mut screen)
+            .unwrap();
+        assert_eq!(screen.height(), if hires { 64 } else { 32 });
+        screen
+    }
+
+    #[test]
+    fn test_draw_wraps_past_bottom_edge() {
+        for (hires, bottom) in [(false, 31), (true, 63)] {
+            let screen = draw_on_bottom_edge(false, hires);
+            assert!(screen.is_set(8, bottom));
+            assert!(screen.is_set(9, 0));
+            assert!(screen.is_set(10, 1));
+            assert_eq!(screen.set_pixels().count(), 3);
+        }
+    }
+
+    #[test]
+    fn test_draw_clips_past_bottom_edge() {
+        for (hires, bottom) in [(false, 31), (true, 63)] {
+            let screen = draw_on_bottom_edge(true, hires);
+            assert!(screen.is_set(8, bottom));
+            assert_eq!(screen.set_pixels().count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_wait_for_key_needs_fresh_press() {
+        let mut screen = Screen::new();
+        let mut keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // F00A - LD V0, K
+        machine.load(&[0xf0, 0x0a]).unwrap();
+
+        // Key 5 was already down before the instruction started waiting.
+        keyboard.press(5);
+        keyboard.tick();
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.pc, 0x200);
+
+        keyboard.release(5);
+        keyboard.tick();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.pc, 0x200);
+
+        keyboard.press(5);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.pc, 0x200);
+
+        // The instruction completes once the fresh press is released.
+        keyboard.tick();
+        keyboard.release(5);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.pc, 0x202);
+        assert_eq!(machine.registers[0], 5);
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 6001 - LD V0, 1
+        // 6102 - LD V1, 2
+        // 6203 - LD V2, 3
+        machine.load(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03]).unwrap();
+        machine.add_breakpoint(0x202);
+
+        assert_eq!(machine.step(&keyboard, &mut screen), Ok(StepOutcome::Ok));
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Ok(StepOutcome::BreakpointHit(0x202))
+        );
+        assert_eq!(machine.pc, 0x202);
+        assert_eq!(machine.registers[1], 0);
+
+        // Stepping again runs the instruction under the breakpoint.
+        assert_eq!(machine.step(&keyboard, &mut screen), Ok(StepOutcome::Ok));
+        assert_eq!(machine.registers[1], 2);
+
+        assert!(machine.remove_breakpoint(0x202));
+        assert!(!machine.remove_breakpoint(0x202));
+    }
+
+    #[test]
+    fn test_load_patched() {
+        let mut machine = Machine::new(Quirks::default());
+        // Turns 6A42 - LD VA, 0x42 into 6A99 - LD VA, 0x99.
+        let patch = [
+            b"PATCH".as_slice(),
+            &[0x00, 0x00, 0x01, 0x00, 0x01, 0x99],
+            b"EOF",
+        ]
+        .concat();
+
+        machine.load_patched(&[0x6a, 0x42], &patch).unwrap();
+        assert_eq!(&machine.memory()[0x200..0x202], &[0x6a, 0x99]);
+
+        assert!(machine.load_patched(&[0x00, 0xe0], b"PATCH").is_err());
+        assert_eq!(&machine.memory()[0x200..0x202], &[0x6a, 0x99]);
+    }
+
+    #[test]
+    fn test_load_reader() {
+        let mut machine = Machine::new(Quirks::default());
+
+        let mut rom: &[u8] = &[0x00, 0xe0, 0x12, 0x00];
+        machine.load_reader(&mut rom).unwrap();
+        assert_eq!(&machine.memory()[0x200..0x204], &[0x00, 0xe0, 0x12, 0x00]);
+
+        let largest = vec![0xaa; 4096 - 0x200];
+        machine.load_reader(&mut largest.as_slice()).unwrap();
+        assert_eq!(machine.memory()[0xfff], 0xaa);
+    }
+
+    #[test]
+    fn test_load_oversized_rom() {
+        let mut machine = Machine::new(Quirks::default());
+
+        let oversized = vec![0xaa; 4096 - 0x200 + 1];
+        let err = machine.load_reader(&mut oversized.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(machine.load(&oversized).is_err());
+        assert_eq!(machine.memory()[0x200], 0);
+    }
+
+    #[test]
+    fn test_write_protection() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // A04E - LD I, 0x04E
+        // F355 - LD [I], V3
+        // F333 - LD B, V3
+        machine.load(&[0xa0, 0x4e, 0xf3, 0x55, 0xf3, 0x33]).unwrap();
+        machine.registers[..4].copy_from_slice(&[1, 2, 3, 200]);
+        machine.protect_font();
+        let font = machine.memory()[FONT_AREA].to_vec();
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Err(MachineError::WriteProtected { addr: 0x050 })
+        );
+        // Nothing was stored, not even the bytes before the font.
+        assert_eq!(machine.memory()[0x4e..0x50], [0, 0]);
+        assert_eq!(machine.memory()[FONT_AREA], font[..]);
+
+        // BCD is covered too, and is fine once the protection is lifted.
+        machine.pc = 0x204;
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Err(MachineError::WriteProtected { addr: 0x050 })
+        );
+        machine.clear_protection();
+        machine.pc = 0x202;
+        machine.run_headless(2, &keyboard, &mut screen).unwrap();
+        assert_eq!(machine.memory()[0x4e..0x52], [2, 0, 0, 200]);
+
+        machine.protect(0x300..0x302);
+        machine.register_i = 0x2ff;
+        machine.pc = 0x202;
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Err(MachineError::WriteProtected { addr: 0x300 })
+        );
+    }
+
+    #[test]
+    fn test_step_over() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 200: 2206 - CALL 0x206
+        // 202: 6107 - LD V1, 7
+        // 204: 1204 - JP 0x204
+        // 206: 220A - CALL 0x20A
+        // 208: 00EE - RET
+        // 20A: 7001 - ADD V0, 1
+        // 20C: 00EE - RET
+        let program = [
+            0x22, 0x06, 0x61, 0x07, 0x12, 0x04, 0x22, 0x0a, 0x00, 0xee, 0x70, 0x01, 0x00, 0xee,
+        ];
+        machine.load(&program).unwrap();
+        machine.pause();
+
+        // The nested calls run to the end and it stops after the CALL.
+        let outcome = machine.step_over(100, &keyboard, &mut screen);
+        assert_eq!(outcome, Ok(Some(StepOutcome::Ok)));
+        assert_eq!((machine.pc(), machine.sp()), (0x202, 0));
+        assert_eq!(machine.registers[0], 1);
+
+        // Anything else is a single step.
+        machine.step_over(100, &keyboard, &mut screen).unwrap();
+        assert_eq!(machine.pc(), 0x204);
+        assert_eq!(machine.registers[1], 7);
+
+        // Stepping into the call stops at its first instruction instead.
+        machine.reset(&mut screen);
+        machine.step_into(&keyboard, &mut screen).unwrap();
+        assert_eq!((machine.pc(), machine.sp()), (0x206, 1));
+
+        // A breakpoint inside the call stops it there.
+        machine.add_breakpoint(0x20a);
+        let outcome = machine.step_over(100, &keyboard, &mut screen);
+        assert_eq!(outcome, Ok(Some(StepOutcome::BreakpointHit(0x20a))));
+
+        // So does running out of steps.
+        machine.remove_breakpoint(0x20a);
+        machine.reset(&mut screen);
+        assert_eq!(machine.step_over(2, &keyboard, &mut screen), Ok(None));
+        assert_eq!((machine.pc(), machine.sp()), (0x20a, 2));
+    }
+
+    #[test]
+    fn test_execute() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        let outcome = machine.execute(Instruction::Set(3, 0x42), &keyboard, &mut screen);
+        assert_eq!(outcome, Ok(StepOutcome::Ok));
+        assert_eq!(machine.registers[3], 0x42);
+        assert_eq!(machine.pc(), 0x202);
+        assert_eq!(machine.cycles(), 1);
+
+        machine
+            .execute(Instruction::LoadSprite(3), &keyboard, &mut screen)
+            .unwrap();
+        machine
+            .execute(Instruction::Drw(0, 0, 5), &keyboard, &mut screen)
+            .unwrap();
+        assert!(screen.is_set(0, 0));
+
+        assert_eq!(
+            machine.execute(Instruction::Jmp(0x206), &keyboard, &mut screen),
+            Ok(StepOutcome::Halted(0x206))
+        );
+        assert_eq!(
+            machine.execute(Instruction::Ret, &keyboard, &mut screen),
+            Err(MachineError::StackUnderflow { pc: 0x206 })
+        );
+
+        // XO-CHIP instructions still need XO-CHIP mode.
+        let err = machine
+            .execute(Instruction::SetPitch(1), &keyboard, &mut screen)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MachineError::Decode(DecodeError {
+                opcode: 0xf13a,
+                pc: Some(0x208)
+            })
+        );
+    }
+
+    #[test]
+    fn test_load_at() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 6A42 - LD VA, 0x42
+        // 1602 - JP 0x602
+        machine.load_at(&[0x6a, 0x42, 0x16, 0x02], 0x600).unwrap();
+        assert_eq!((machine.pc(), machine.entry_point()), (0x600, 0x600));
+        assert_eq!(machine.memory()[0x200], 0);
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[0xa], 0x42);
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Ok(StepOutcome::Halted(0x602))
+        );
+
+        // Resetting starts over from the same place.
+        machine.reset(&mut screen);
+        assert_eq!(machine.pc(), 0x600);
+        assert_eq!(machine.memory()[0x600], 0x6a);
+        assert!(machine.analyze().is_code(0x602));
+
+        assert_eq!(
+            machine.load_at(&[0; 3], 0xffe).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+        for rom in [&[][..], &[0; 3]] {
+            assert_eq!(
+                machine.load_at(rom, 0x1001).unwrap_err().kind(),
+                std::io::ErrorKind::InvalidInput
+            );
+        }
+        assert_eq!(machine.entry_point(), 0x600);
+    }
+
+    #[test]
+    fn test_load_with_profiles() {
+        let quirks = Quirks {
+            jump_with_vx: true,
+            ..Quirks::default()
+        };
+
+        let mut machine = Machine::new(quirks);
+        let applied = machine
+            .load_with_profiles(BuiltinRom::IbmLogo.bytes(), &Profiles::default())
+            .unwrap();
+        assert_eq!(applied, Quirks::cosmac_vip());
+        assert_eq!(machine.quirks(), Quirks::cosmac_vip());
+
+        // Unknown ROMs keep the quirks the machine was created with.
+        let mut machine = Machine::new(quirks);
+        let applied = machine
+            .load_with_profiles(BuiltinRom::OpcodeTest.bytes(), &Profiles::default())
+            .unwrap();
+        assert_eq!(applied, quirks);
+    }
+
+    #[test]
+    fn test_fetch_at_top_of_memory() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        assert_eq!(machine.memory().len(), 4096);
+
+        // 6042 - LD V0, 0x42 in the very last word.
+        machine.ram[0xffe..].copy_from_slice(&[0x60, 0x42]);
+        machine.pc = 0xffe;
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[0], 0x42);
+
+        machine.pc = 0xfff;
+        let err = machine.step(&keyboard, &mut screen).unwrap_err();
+        assert_eq!(err, MachineError::OutOfBounds { addr: 0x1000 });
+        assert_eq!(machine.pc, 0xfff);
+
+        machine.pc = 0x1000;
+        let err = machine.step(&keyboard, &mut screen).unwrap_err();
+        assert_eq!(err, MachineError::OutOfBounds { addr: 0x1001 });
+    }
+
+    #[test]
+    fn test_pause_and_step_once() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 7001 - ADD V0, 1
+        // 1200 - JP 0x200
+        machine.load(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        machine.register_delay = 10;
+
+        machine.pause();
+        assert!(machine.is_paused());
+
+        machine.last_tick -= std::time::Duration::from_micros(super::TIMER_RATE);
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Ok(StepOutcome::Paused)
+        );
+        assert_eq!(machine.pc, 0x200);
+
+        assert_eq!(
+            machine.step_once(&keyboard, &mut screen),
+            Ok(StepOutcome::Ok)
+        );
+        assert_eq!(machine.pc, 0x202);
+        assert_eq!(machine.registers[0], 1);
+        assert_eq!(machine.register_delay, 10);
+
+        machine.resume();
+        assert!(!machine.is_paused());
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.pc, 0x200);
+    }
+
+    #[test]
+    fn test_flag_wins_when_vf_is_destination() {
+        // 8FE4 - ADD VF, VE: 0xff + 0x02 carries.
+        let m = MachineBuilder::new()
+            .program(&[0x8f, 0xe4])
+            .reg(0xf, 0xff)
+            .reg(0xe, 0x02)
+            .run(1);
+        assert_eq!(m.registers[0xf], 1);
+
+        // 8FE4 - ADD VF, VE: 0x01 + 0x02 doesn't carry.
+        let m = MachineBuilder::new()
+            .program(&[0x8f, 0xe4])
+            .reg(0xf, 0x01)
+            .reg(0xe, 0x02)
+            .run(1);
+        assert_eq!(m.registers[0xf], 0);
+
+        // 8FE5 - SUB VF, VE: 0x05 - 0x02 doesn't borrow.
+        let m = MachineBuilder::new()
+            .program(&[0x8f, 0xe5])
+            .reg(0xf, 0x05)
+            .reg(0xe, 0x02)
+            .run(1);
+        assert_eq!(m.registers[0xf], 1);
+
+        // 8FE5 - SUB VF, VE: 0x02 - 0x05 borrows.
+        let m = MachineBuilder::new()
+            .program(&[0x8f, 0xe5])
+            .reg(0xf, 0x02)
+            .reg(0xe, 0x05)
+            .run(1);
+        assert_eq!(m.registers[0xf], 0);
+
+        // 8FE7 - SUBN VF, VE: 0x05 - 0x02 doesn't borrow.
+        let m = MachineBuilder::new()
+            .program(&[0x8f, 0xe7])
+            .reg(0xf, 0x02)
+            .reg(0xe, 0x05)
+            .run(1);
+        assert_eq!(m.registers[0xf], 1);
+
+        // 8FF6 - SHR VF and 8FFE - SHL VF keep the shifted out bit.
+        let m = MachineBuilder::new()
+            .program(&[0x8f, 0xf6])
+            .reg(0xf, 0x03)
+            .run(1);
+        assert_eq!(m.registers[0xf], 1);
+        let m = MachineBuilder::new()
+            .program(&[0x8f, 0xfe])
+            .reg(0xf, 0x40)
+            .run(1);
+        assert_eq!(m.registers[0xf], 0);
+    }
+
+    #[test]
+    fn test_quirk_display_wait() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let quirks = Quirks {
+            display_wait: true,
+            ..Quirks::default()
+        };
+        let mut machine = Machine::new(quirks);
+
+        // D005 - DRW V0, V0, 5
+        // 6105 - LD V1, 5
+        machine.load(&[0xd0, 0x05, 0x61, 0x05]).unwrap();
+        machine.last_tick = std::time::Instant::now();
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.pc(), 0x202);
+        assert_eq!(machine.registers[1], 0);
+
+        // Once the next 60 Hz tick is due the CPU carries on.
+        machine.last_tick -= std::time::Duration::from_micros(TIMER_RATE);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[1], 5);
+
+        // Without the quirk nothing waits.
+        let m = MachineBuilder::new()
+            .program(&[0xd0, 0x05, 0x61, 0x05])
+            .run(2);
+        assert_eq!(m.registers[1], 5);
+    }
+
+    #[test]
+    fn test_cycles() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        assert_eq!(machine.cycles(), 0);
+
+        // 7001 - ADD V0, 1
+        // 1200 - JP 0x200
+        machine.load(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        for _ in 0..25 {
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+        assert_eq!(machine.cycles(), 25);
+
+        // Paused steps don't execute anything, so they aren't counted.
+        machine.pause();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.cycles(), 25);
+    }
+
+    #[test]
+    fn test_last_cost() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_cost_model(CostModel {
+            draw: 3,
+            draw_per_row: 2,
+        });
+        assert_eq!(machine.last_cost(), 0);
+
+        // 7001 - ADD V0, 1
+        // D015 - DRW V0, V1, 5
+        machine.load(&[0x70, 0x01, 0xd0, 0x15]).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.last_cost(), 1);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.last_cost(), 13);
+
+        machine.pause();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.last_cost(), 1);
+    }
+
+    #[test]
+    fn test_trace_hook() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        let trace = Rc::new(RefCell::new(Vec::new()));
+        let recorded = trace.clone();
+        machine.set_trace_hook(Box::new(move |pc, opcode, ins| {
+            recorded.borrow_mut().push((pc, opcode, *ins));
+        }));
+
+        // 6005 - LD V0, 5
+        // 7001 - ADD V0, 1
+        // 1200 - JP 0x200
+        machine.load(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x00]).unwrap();
+        machine.run_headless(4, &keyboard, &mut screen).unwrap();
+
+        assert_eq!(
+            *trace.borrow(),
+            vec![
+                (0x200, 0x6005, Instruction::Set(0, 5)),
+                (0x202, 0x7001, Instruction::Add(0, 1)),
+                (0x204, 0x1200, Instruction::Jmp(0x200)),
+                (0x200, 0x6005, Instruction::Set(0, 5)),
+            ]
+        );
+
+        machine.clear_trace_hook();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(trace.borrow().len(), 4);
+    }
+
+    #[test]
+    fn test_misaligned_jump() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 1205 - JP 0x205
+        machine.load(&[0x12, 0x05]).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.pc(), 0x205);
+
+        machine.reset(&mut screen);
+        machine.set_strict_alignment(true);
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Err(MachineError::MisalignedJump {
+                pc: 0x200,
+                target: 0x205
+            })
+        );
+        // Like other faults the jump is skipped.
+        assert_eq!(machine.pc(), 0x202);
+    }
+
+    #[test]
+    fn test_misaligned_return() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_strict_alignment(true);
+
+        // 00EE - RET, to an odd address pushed by hand.
+        machine.load(&[0x00, 0xee]).unwrap();
+        machine.stack[0] = 0x301;
+        machine.sp = 1;
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Err(MachineError::MisalignedJump {
+                pc: 0x200,
+                target: 0x301
+            })
+        );
+        assert_eq!(machine.sp(), 1);
+    }
+
+    /// RAM with a read-only register at 0xF00 that always reads 0x42.
+    struct MappedRegister(Vec<u8>);
+
+    impl std::ops::Deref for MappedRegister {
+        type Target = [u8];
+
+        fn deref(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    impl std::ops::DerefMut for MappedRegister {
+        fn deref_mut(&mut self) -> &mut [u8] {
+            &mut self.0
+        }
+    }
+
+    impl Memory for MappedRegister {
+        fn resize(&mut self, len: usize) {
+            self.0.resize(len, 0);
+        }
+
+        fn read(&mut self, addr: usize) -> u8 {
+            match addr {
+                0xf00 => 0x42,
+                _ => self.0[addr],
+            }
+        }
+
+        fn write(&mut self, addr: usize, value: u8) {
+            if addr != 0xf00 {
+                self.0[addr] = value;
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_memory() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::with_memory(Quirks::default(), MappedRegister(Vec::new()));
+        assert_eq!(machine.memory().len(), 4096);
+
+        // AEFF - LD I, 0xEFF
+        // F165 - LD V1, [I]
+        machine.load(&[0xae, 0xff, 0xf1, 0x65]).unwrap();
+        machine.ram[0xeff] = 0x17;
+        machine.run_headless(2, &keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[0], 0x17);
+        assert_eq!(machine.registers[1], 0x42);
+        // The bytes underneath are untouched.
+        assert_eq!(machine.memory()[0xf00], 0);
+
+        machine.set_xo_chip(true);
+        assert_eq!(machine.memory().len(), 0x10000);
+    }
+
+    #[test]
+    fn test_sound_hook() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let recorded = changes.clone();
+        machine.set_sound_hook(Box::new(move |on| recorded.borrow_mut().push(on)));
+
+        // 6002 - LD V0, 2
+        // F018 - LD ST, V0
+        // 1204 - JP 0x204
+        machine.load(&[0x60, 0x02, 0xf0, 0x18, 0x12, 0x04]).unwrap();
+        machine.run_headless(10, &keyboard, &mut screen).unwrap();
+        assert_eq!(*changes.borrow(), [true]);
+
+        machine.last_tick -= std::time::Duration::from_micros(2 * TIMER_RATE);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(*changes.borrow(), [true, false]);
+    }
+
+    #[test]
+    fn test_pause_silences_sound() {
+        let mut machine = Machine::new(Quirks::default());
+
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let recorded = changes.clone();
+        machine.set_sound_hook(Box::new(move |on| recorded.borrow_mut().push(on)));
+
+        machine.set_sound_timer(30);
+        machine.pause();
+        assert_eq!(*changes.borrow(), [true, false]);
+
+        // The timer is left alone, so the beep picks up again on resuming.
+        assert_eq!(machine.sound_timer(), 30);
+        machine.resume();
+        assert_eq!(*changes.borrow(), [true, false, true]);
+    }
+
+    #[test]
+    fn test_xo_chip_long_load() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+
+        // F000 1234 - LD I, long 0x1234
+        // 3000      - SE V0, 0
+        // F000 FFFE - LD I, long 0xFFFE
+        // 6105      - LD V1, 5
+        let program = [
+            0xf0, 0x00, 0x12, 0x34, 0x30, 0x00, 0xf0, 0x00, 0xff, 0xfe, 0x61, 0x05,
+        ];
+
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_xo_chip(true);
+        assert_eq!(machine.memory().len(), 0x10000);
+        machine.load(&program).unwrap();
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.i(), 0x1234);
+        assert_eq!(machine.pc(), 0x204);
+
+        // Skipping jumps over both words of the long load.
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.pc(), 0x20a);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[1], 5);
+        assert_eq!(machine.i(), 0x1234);
+
+        assert_eq!(
+            machine.disassemble(0x200, 3),
+            vec![
+                (0x200, "LD I, long 0x1234".to_string()),
+                (0x204, "SE V0, 0x00".to_string()),
+                (0x206, "LD I, long 0xFFFE".to_string()),
+            ]
+        );
+
+        // Classic machines keep 4KB and don't know F000.
+        let mut machine = Machine::new(Quirks::default());
+        assert_eq!(machine.memory().len(), 4096);
+        machine.load(&program).unwrap();
+        let err = machine.step(&keyboard, &mut screen).unwrap_err();
+        assert!(matches!(err, MachineError::Decode(_)));
+        assert_eq!(machine.pc(), 0x202);
+    }
+
+    #[test]
+    fn test_xo_chip_planes() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_xo_chip(true);
+
+        // F301      - PLANE 3
+        // A300      - LD I, 0x300
+        // D001      - DRW V0, V0, 1
+        // F201      - PLANE 2
+        // D001      - DRW V0, V0, 1
+        machine
+            .load(&[0xf3, 0x01, 0xa3, 0x00, 0xd0, 0x01, 0xf2, 0x01, 0xd0, 0x01])
+            .unwrap();
+        // One byte for the first plane, one for the second.
+        machine.ram[0x300..0x302].copy_from_slice(&[0b1100_0000, 0b1010_0000]);
+
+        machine.run_headless(3, &keyboard, &mut screen).unwrap();
+        assert_eq!(screen.selected_planes(), 0b11);
+        assert_eq!(screen.planes(0, 0), 0b11);
+        assert_eq!(screen.planes(1, 0), 0b01);
+        assert_eq!(screen.planes(2, 0), 0b10);
+        assert_eq!(machine.registers[0xf], 0);
+
+        // Drawing the second plane's byte again only collides in that plane.
+        machine.ram[0x300] = 0b1010_0000;
+        machine.run_headless(2, &keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[0xf], 1);
+        assert_eq!(screen.planes(0, 0), 0b01);
+        assert_eq!(screen.planes(1, 0), 0b01);
+        assert_eq!(screen.planes(2, 0), 0b00);
+
+        // Classic ROMs can't select planes.
+        let mut machine = Machine::new(Quirks::default());
+        machine.load(&[0xf3, 0x01]).unwrap();
+        assert!(machine.step(&keyboard, &mut screen).is_err());
+    }
+
+    #[test]
+    fn test_seeded_rnd() {
+        // C0FF - RND V0, 0xFF
+        // C10F - RND V1, 0x0F
+        let setup = MachineBuilder::new()
+            .program(&[0xc0, 0xff, 0xc1, 0x0f])
+            .seed(42);
+        let m = setup.clone().run(2);
+        assert_eq!((m.registers[0], m.registers[1]), (162, 3));
+
+        let again = setup.run(2);
+        assert_eq!(again.registers[..2], m.registers[..2]);
+    }
+
+    #[test]
+    fn test_add_i_overflow() {
+        // F01E - ADD I, V0
+        let setup = MachineBuilder::new().program(&[0xf0, 0x1e]).reg(0, 0x20);
+
+        let m = setup.clone().i(0xfff0).run(1);
+        assert_eq!(m.i(), 0x0010);
+        assert_eq!(m.registers[0xf], 0);
+
+        let setup = setup.quirks(Quirks {
+            add_i_sets_vf: true,
+            ..Quirks::default()
+        });
+        let m = setup.clone().i(0x0ff0).run(1);
+        assert_eq!(m.i(), 0x1010);
+        assert_eq!(m.registers[0xf], 1);
+
+        let m = setup.i(0x0100).reg(0xf, 1).run(1);
+        assert_eq!(m.i(), 0x0120);
+        assert_eq!(m.registers[0xf], 0);
+    }
+
+    #[test]
+    fn test_draw_sprite_past_end_of_memory() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // DEEF - DRW VE, VE, 15
+        machine.load(&[0xde, 0xef, 0xde, 0xef]).unwrap();
+        machine.register_i = 0xffa;
+        machine.ram[0xfff] = 0xff;
+        machine.ram[0] = 0xf0;
+
+        // By default the draw faults without touching the screen or VF.
+        machine.registers[0xf] = 7;
+        let err = machine.step(&keyboard, &mut screen).unwrap_err();
+        assert_eq!(err, MachineError::OutOfBounds { addr: 0x1000 });
+        assert_eq!(machine.registers[0xf], 7);
+        assert!(!screen.is_set(0, 5));
+
+        machine.set_wrap_memory(true);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert!((0..8).all(|x| screen.is_set(x, 5)));
+        // The last rows wrap around to the start of memory.
+        assert!((0..4).all(|x| screen.is_set(x, 6)));
+        assert!(!screen.is_set(4, 6));
+    }
+
+    #[test]
+    fn test_store_and_load_at_end_of_memory() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // F233 - LD B, V2
+        // F155 - LD [I], V1
+        // F165 - LD V1, [I]
+        let program = [0xf2, 0x33, 0xf1, 0x55, 0xf1, 0x65];
+        machine.load(&program).unwrap();
+        machine.registers[..3].copy_from_slice(&[0xaa, 0xbb, 123]);
+        machine.register_i = 0xfff;
+
+        for pc in [0x202, 0x204, 0x206] {
+            let err = machine.step(&keyboard, &mut screen).unwrap_err();
+            assert_eq!(err, MachineError::OutOfBounds { addr: 0x1000 });
+            // The faulting instruction is skipped without a partial access.
+            assert_eq!(machine.pc, pc);
+            assert_eq!(machine.ram[0xfff], 0);
+            assert_eq!(machine.ram[0], 0);
+        }
+
+        machine.set_wrap_memory(true);
+        machine.pc = 0x200;
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(
+            (machine.ram[0xfff], machine.ram[0], machine.ram[1]),
+            (1, 2, 3)
+        );
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!((machine.ram[0xfff], machine.ram[0]), (0xaa, 0xbb));
+
+        machine.registers[..2].copy_from_slice(&[0, 0]);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[..2], [0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn test_fetch_wraps_at_top_of_memory() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_wrap_memory(true);
+
+        // 6042 - LD V0, 0x42 split across the end and the start of memory.
+        machine.ram[0xfff] = 0x60;
+        machine.ram[0] = 0x42;
+        machine.pc = 0xfff;
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[0], 0x42);
+
+        // 6143 - LD V1, 0x43, reached by running past the end.
+        machine.ram[1..3].copy_from_slice(&[0x61, 0x43]);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[1], 0x43);
+        assert_eq!(machine.pc, 3);
+    }
+
+    #[test]
+    fn test_halt_and_run_until() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 1200 - JP 0x200
+        machine.load(&[0x12, 0x00]).unwrap();
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Ok(StepOutcome::Halted(0x200))
+        );
+        assert_eq!(
+            machine.run_headless(1000, &keyboard, &mut screen),
+            Ok(StepOutcome::Halted(0x200))
+        );
+        assert_eq!(machine.cycles(), 2);
+
+        // 7001 - ADD V0, 1
+        // 1200 - JP 0x200
+        let mut machine = Machine::new(Quirks::default());
+        machine.load(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let outcome = machine.run_until(1000, &keyboard, &mut screen, |m| m.register(0) == 10);
+        assert_eq!(outcome, Ok(Some(StepOutcome::Ok)));
+        assert_eq!(machine.register(0), 10);
+        assert_eq!(machine.cycles(), 19);
+
+        let outcome = machine.run_until(6, &keyboard, &mut screen, |_| false);
+        assert_eq!(outcome, Ok(None));
+        assert_eq!(machine.cycles(), 25);
+    }
+
+    #[test]
+    fn test_timer_speed() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 1200 - JP 0x200
+        machine.load(&[0x12, 0x00]).unwrap();
+        machine.register_delay = 10;
+        machine.set_timer_speed(4);
+
+        // A quarter of a 60 Hz period is enough for a tick now.
+        machine.last_tick -= std::time::Duration::from_micros(TIMER_RATE / 4 + 1);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.register_delay, 9);
+
+        machine.set_timer_speed(1);
+        machine.last_tick -= std::time::Duration::from_micros(TIMER_RATE / 4 + 1);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.register_delay, 9);
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_rewind_limit(3);
+
+        // 7001 - ADD V0, 1
+        // 1200 - JP 0x200
+        machine.load(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        let mut frames = Vec::new();
+        for _ in 0..5 {
+            machine.record_history(&screen);
+            frames.push(machine.save_state(&screen));
+            machine.run_headless(10, &keyboard, &mut screen).unwrap();
+        }
+
+        assert!(machine.rewind(&mut screen));
+        assert!(machine.rewind(&mut screen));
+        assert_eq!(machine.save_state(&screen), frames[3]);
+        assert_eq!(machine.register(0), 15);
+
+        // Only the last three frames were kept.
+        assert!(machine.rewind(&mut screen));
+        assert_eq!(machine.save_state(&screen), frames[2]);
+        assert!(!machine.rewind(&mut screen));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.add_watchpoint(0x302, WatchKind::Write);
+        machine.add_watchpoint(0x310, WatchKind::ReadWrite);
+
+        // A300 - LD I, 0x300
+        // F355 - LD [I], V3
+        // F365 - LD V3, [I]
+        // A310 - LD I, 0x310
+        // F065 - LD V0, [I]
+        machine
+            .load(&[0xa3, 0x00, 0xf3, 0x55, 0xf3, 0x65, 0xa3, 0x10, 0xf0, 0x65])
+            .unwrap();
+        machine.registers[2] = 0x42;
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(
+            machine.step(&keyboard, &mut screen),
+            Ok(StepOutcome::WatchpointHit(0x302, WatchKind::Write))
+        );
+        // The store still happened.
+        assert_eq!(machine.memory()[0x302], 0x42);
+
+        // Reads of a write-only watchpoint don't stop.
+        assert_eq!(machine.step(&keyboard, &mut screen), Ok(StepOutcome::Ok));
+
+        assert_eq!(
+            machine.run_headless(5, &keyboard, &mut screen),
+            Ok(StepOutcome::WatchpointHit(0x310, WatchKind::Read))
+        );
+
+        assert!(machine.remove_watchpoint(0x310));
+        assert!(!machine.remove_watchpoint(0x310));
+    }
+
+    #[test]
+    fn test_load_sprite_masks_digit() {
+        // F029 - LD F, V0
+        let m = MachineBuilder::new()
+            .program(&[0xf0, 0x29])
+            .reg(0, 0x0b)
+            .run(1);
+        assert_eq!(m.i() as usize, FONT_START + 0x0b * 5);
+
+        let m = MachineBuilder::new()
+            .program(&[0xf0, 0x29])
+            .reg(0, 0xfa)
+            .run(1);
+        assert_eq!(m.i() as usize, FONT_START + 0x0a * 5);
+    }
+
+    #[test]
+    fn test_load_sprite_font_start() {
+        // F229 - LD F, V2
+        let m = MachineBuilder::new()
+            .program(&[0xf2, 0x29])
+            .reg(2, 2)
+            .run(1);
+        assert_eq!(m.i() as usize, FONT_START + 10);
+        assert_eq!(m.memory()[FONT_START + 10], 0b11110000);
+        assert_eq!(m.memory()[0], 0);
+    }
+
+    #[test]
+    fn test_load_big_sprite() {
+        // F330 - LD HF, V3
+        let m = MachineBuilder::new()
+            .program(&[0xf3, 0x30])
+            .reg(3, 7)
+            .run(1);
+        assert_eq!(m.i() as usize, BIG_FONT_START + 70);
+        assert_eq!(BIG_FONT_START, 0x0a0);
+        assert_eq!(
+            &m.memory()[BIG_FONT_START + 70..BIG_FONT_START + 72],
+            &[0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn test_rpl_flags_round_trip() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // F275 - LD R, V2
+        // 6000 - LD V0, 0
+        // 6100 - LD V1, 0
+        // 6200 - LD V2, 0
+        // F185 - LD V1, R
+        machine
+            .load(&[0xf2, 0x75, 0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0xf1, 0x85])
+            .unwrap();
+        machine.registers[..3].copy_from_slice(&[1, 2, 3]);
+
+        for _ in 0..5 {
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+        assert_eq!(machine.rpl_flags(), [1, 2, 3, 0, 0, 0, 0, 0]);
+        assert_eq!(&machine.registers()[..3], &[1, 2, 0]);
+    }
+
+    #[test]
+    fn test_rpl_flags_cap_at_seven() {
+        // FF75 - LD R, VF
+        let m = MachineBuilder::new()
+            .program(&[0xff, 0x75])
+            .regs(&[9; 16])
+            .run(1);
+        assert_eq!(m.rpl_flags(), [9; 8]);
+    }
+
+    #[test]
+    fn test_every_opcode_steps_without_panicking() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+
+        let every_quirk = Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_with_vx: true,
+            vf_reset_on_logic: true,
+            add_i_sets_vf: true,
+            display_wait: true,
+            clip_sprites: true,
+        };
+
+        for (quirks, xo_chip) in [
+            (Quirks::default(), false),
+            (every_quirk, false),
+            (Quirks::default(), true),
+        ] {
+            let mut machine = Machine::new(quirks);
+            machine.set_xo_chip(xo_chip);
+            for opcode in 0..=u16::MAX {
+                machine.load(&opcode.to_be_bytes()).unwrap();
+                // Push everything that indexes memory or the screen to its limit.
+                machine.registers = [0xff; 16];
+                machine.register_i = (machine.memory().len() - 1) as u16;
+                machine.waiting_key = None;
+                machine.waiting_vblank = false;
+                let _ = machine.step(&keyboard, &mut screen);
+                machine.pc = 0x200;
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_cache_self_modifying_code() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_decode_cache(true);
+
+        // 200: 6001 - LD V0, 1
+        // 202: A208 - LD I, 0x208
+        // 204: F155 - LD [I], V1     overwrites 208 with V0 V1
+        // 206: 1208 - JP 0x208
+        // 208: 6100 - LD V1, 0       becomes 6142 - LD V1, 0x42 the second time round
+        // 20A: 1200 - JP 0x200
+        machine
+            .load(&[
+                0x60, 0x01, 0xa2, 0x08, 0xf1, 0x55, 0x12, 0x08, 0x61, 0x00, 0x12, 0x00,
+            ])
+            .unwrap();
+
+        // Decode 208 once before it's rewritten.
+        machine.pc = 0x208;
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[1], 0);
+
+        machine.pc = 0x200;
+        machine.registers[0] = 0x61;
+        machine.registers[1] = 0x42;
+        for _ in 0..5 {
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+        assert_eq!(machine.registers[1], 0x42);
+    }
+
+    #[test]
+    fn test_decode_cache_load_invalidates() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_decode_cache(true);
+
+        // 6001 - LD V0, 1
+        machine.load(&[0x60, 0x01]).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[0], 1);
+
+        // 6002 - LD V0, 2
+        machine.pc = 0x200;
+        machine.load(&[0x60, 0x02]).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[0], 2);
+    }
+
+    #[test]
+    fn test_last_collisions() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 200: A20A - LD I, 0x20A
+        // 202: D011 - DRW V0, V0, 1      draws 11110000 at (0, 0)
+        // 204: A20B - LD I, 0x20B
+        // 206: D011 - DRW V0, V0, 1      draws 00111100 over it
+        // 208: D011 - DRW V0, V0, 1      and again, erasing it
+        // 20A: F0 3C - sprite data
+        machine
+            .load(&[
+                0xa2, 0x0a, 0xd0, 0x11, 0xa2, 0x0b, 0xd0, 0x11, 0xd0, 0x11, 0xf0, 0x3c,
+            ])
+            .unwrap();
+
+        for _ in 0..2 {
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+        assert!(screen.last_collisions().is_empty());
+
+        for _ in 0..2 {
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+        assert_eq!(machine.registers[0xf], 1);
+        assert_eq!(screen.last_collisions(), &[(2, 0), (3, 0)]);
+
+        // Only (4, 0) and (5, 0) are still lit from the second sprite.
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(screen.last_collisions(), &[(4, 0), (5, 0)]);
+
+        screen.decay();
+        assert!(screen.last_collisions().is_empty());
+    }
+
+    #[test]
+    fn test_last_draw_collisions() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_xo_chip(true);
+
+        // 200: F301 - PLANE 3
+        // 202: A20C - LD I, 0x20C
+        // 204: D011 - DRW V0, V1, 1      11110000 in plane 1, 00001111 in plane 2
+        // 206: A20E - LD I, 0x20E
+        // 208: D011 - DRW V0, V1, 1      00111100 in both planes
+        // 20A: 120A - JP 0x20A
+        // 20C: F0 0F 3C 3C - sprite data
+        machine
+            .load(&[
+                0xf3, 0x01, 0xa2, 0x0c, 0xd0, 0x11, 0xa2, 0x0e, 0xd0, 0x11, 0x12, 0x0a, 0xf0, 0x0f,
+                0x3c, 0x3c,
+            ])
+            .unwrap();
+
+        machine.run_headless(3, &keyboard, &mut screen).unwrap();
+        assert_eq!(machine.last_draw_collisions(), DrawCollisions::default());
+
+        machine.run_headless(2, &keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[0xf], 1);
+        // Plane 1 collides at x = 2 and 3, plane 2 at 4 and 5.
+        assert_eq!(
+            machine.last_draw_collisions(),
+            DrawCollisions {
+                pixels: 4,
+                planes: 0b11
+            }
+        );
+
+        // Other instructions leave it alone.
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.last_draw_collisions().pixels, 4);
+    }
+
+    #[test]
+    fn test_update_timers_catches_up() {
+        let mut machine = Machine::new(Quirks::default());
+        machine.register_delay = 10;
+        machine.register_sound = 2;
+
+        machine.update_timers();
+        assert_eq!(machine.register_delay, 10);
+
+        // Three and a half periods.
+        machine.last_tick -= std::time::Duration::from_micros(TIMER_RATE * 7 / 2);
+        machine.update_timers();
+        assert_eq!(machine.register_delay, 7);
+        assert_eq!(machine.register_sound, 0);
+
+        // The half period left over counts towards the next tick.
+        machine.last_tick -= std::time::Duration::from_micros(TIMER_RATE * 2 / 3);
+        machine.update_timers();
+        assert_eq!(machine.register_delay, 6);
+
+        machine.pause();
+        machine.last_tick -= std::time::Duration::from_micros(TIMER_RATE * 2);
+        machine.update_timers();
+        assert_eq!(machine.register_delay, 6);
+    }
+
+    #[test]
+    fn test_opcode_counts() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+
+        // 6005 - LD V0, 5
+        // 70FF - ADD V0, 0xFF
+        // 3000 - SE V0, 0
+        // 1202 - JP 0x202
+        let program = [0x60, 0x05, 0x70, 0xff, 0x30, 0x00, 0x12, 0x02];
+        machine.load(&program).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert!(machine.opcode_counts().is_empty());
+
+        machine.set_opcode_counts(true);
+        machine.run_headless(13, &keyboard, &mut screen).unwrap();
+        let counts = machine.opcode_counts();
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts["7xkk"], 5);
+        assert_eq!(counts["3xkk"], 4);
+        assert_eq!(counts["1nnn"], 4);
+
+        machine.set_opcode_counts(false);
+        assert!(machine.opcode_counts().is_empty());
+    }
+
+    #[test]
+    fn test_lockstep_timers() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_timing_mode(TimingMode::Lockstep {
+            cycles_per_frame: 10,
+        });
+
+        // 60FF - LD V0, 0xFF
+        // F015 - LD DT, V0
+        // 7101 - ADD V1, 1
+        // 1204 - JP 0x204
+        let program = [0x60, 0xff, 0xf0, 0x15, 0x71, 0x01, 0x12, 0x04];
+        machine.load(&program).unwrap();
+
+        // The wall clock doesn't come into it.
+        machine.last_tick -= std::time::Duration::from_micros(TIMER_RATE * 100);
+        machine.run_headless(9, &keyboard, &mut screen).unwrap();
+        assert_eq!(machine.delay_timer(), 0xff);
+
+        // The tenth step ends the first frame, then one tick every ten steps.
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.delay_timer(), 0xfe);
+        machine.run_headless(95, &keyboard, &mut screen).unwrap();
+        assert_eq!(machine.delay_timer(), 0xf5);
+
+        // Starting over gives the same result.
+        machine.reset(&mut screen);
+        machine.run_headless(105, &keyboard, &mut screen).unwrap();
+        assert_eq!(machine.delay_timer(), 0xf5);
+    }
+
+    #[test]
+    fn test_lockstep_display_wait() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks {
+            display_wait: true,
+            ..Quirks::default()
+        });
+        machine.set_timing_mode(TimingMode::Lockstep {
+            cycles_per_frame: 4,
+        });
+
+        // D001 - DRW V0, V0, 1
+        // 6105 - LD V1, 5
+        machine.load(&[0xd0, 0x01, 0x61, 0x05]).unwrap();
+
+        // The draw and two waiting steps, the third ends the frame and carries on.
+        machine.run_headless(3, &keyboard, &mut screen).unwrap();
+        assert_eq!(machine.pc(), 0x202);
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[1], 5);
+    }
+
+    #[test]
+    fn test_manual_timers() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_timing_mode(TimingMode::Manual);
+
+        // F015 - LD DT, V0
+        // F118 - LD ST, V1
+        machine.load(&[0xf0, 0x15, 0xf1, 0x18]).unwrap();
+        machine.registers[0] = 3;
+        machine.registers[1] = 1;
+        machine.step(&keyboard, &mut screen).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.delay_timer(), 3);
+        assert_eq!(machine.sound_timer(), 1);
+
+        // However long it's been, only ticks count the timers down.
+        machine.last_tick -= std::time::Duration::from_micros(TIMER_RATE * 10);
+        machine.update_timers();
+        assert_eq!(machine.delay_timer(), 3);
+
+        machine.tick();
+        assert_eq!((machine.delay_timer(), machine.sound_timer()), (2, 0));
+        machine.tick();
+        machine.tick();
+        machine.tick();
+        assert_eq!(machine.delay_timer(), 0);
+
+        machine.set_delay_timer(5);
+        machine.set_sound_timer(4);
+        assert_eq!((machine.delay_timer(), machine.sound_timer()), (5, 4));
+        assert!(machine.beeping);
+
+        machine.pause();
+        machine.tick();
+        assert_eq!(machine.delay_timer(), 5);
+    }
+
+    #[test]
+    fn test_poke_and_dump_memory() {
+        let mut machine = Machine::new(Quirks::default());
+
+        machine.poke(0x300, &[1, 2, 3]).unwrap();
+        assert_eq!(machine.dump_memory(0x2ff, 5).unwrap(), vec![0, 1, 2, 3, 0]);
+
+        machine.poke(0xffe, &[0xaa, 0xbb]).unwrap();
+        assert_eq!(machine.dump_memory(0xffe, 2).unwrap(), vec![0xaa, 0xbb]);
+        assert_eq!(machine.dump_memory(0x1000, 0).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_poke_out_of_bounds() {
+        let mut machine = Machine::new(Quirks::default());
+
+        assert_eq!(
+            machine.poke(0xfff, &[1, 2]),
+            Err(MachineError::OutOfBounds { addr: 0x1000 })
+        );
+        assert_eq!(machine.memory()[0xfff], 0);
+        assert_eq!(
+            machine.dump_memory(0x2000, 1),
+            Err(MachineError::OutOfBounds { addr: 0x2000 })
+        );
+        assert!(machine.dump_memory(0x10, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_poke_invalidates_decode_cache() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_decode_cache(true);
+
+        // 6001 - LD V0, 1
+        machine.load(&[0x60, 0x01]).unwrap();
+        machine.step(&keyboard, &mut screen).unwrap();
+
+        // 6002 - LD V0, 2
+        machine.poke(0x201, &[0x02]).unwrap();
+        machine.pc = 0x200;
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[0], 2);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut screen = Screen::new();
+        let keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_font(&[0xaa; 80]);
+
+        // 6105 - LD V1, 5
+        // A300 - LD I, 0x300
+        // F155 - LD [I], V1
+        // 2208 - CALL 0x208
+        machine
+            .load(&[0x61, 0x05, 0xa3, 0x00, 0xf1, 0x55, 0x22, 0x08])
+            .unwrap();
+        machine.register_delay = 30;
+        screen.set_hires(true);
+        screen.set(100, 50, 1);
+        for _ in 0..4 {
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+        assert_eq!(machine.pc(), 0x208);
+        assert_eq!(machine.memory()[0x301], 5);
+
+        machine.reset(&mut screen);
+        assert_eq!(machine.pc(), 0x200);
+        assert_eq!(machine.registers(), &[0; 16]);
+        assert_eq!(machine.i(), 0);
+        assert_eq!(machine.sp(), 0);
+        assert_eq!(machine.register_delay, 0);
+        assert_eq!(machine.cycles(), 0);
+        // The ROM is back and what it wrote is gone.
+        assert_eq!(&machine.memory()[0x200..0x202], &[0x61, 0x05]);
+        assert_eq!(machine.memory()[0x301], 0);
+        assert_eq!(machine.memory()[FONT_START], 0xaa);
+        assert!(!screen.is_hires());
+        assert!(!screen.is_set(10, 10));
+
+        machine.step(&keyboard, &mut screen).unwrap();
+        assert_eq!(machine.registers[1], 5);
+    }
+
+    #[test]
+    fn test_reset_after_shrinking_memory() {
+        let mut screen = Screen::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.set_xo_chip(true);
+        machine.load(&[0x12; 0x2000]).unwrap();
+
+        // The ROM no longer fits in 4KB, so there's nothing left to restart.
+        machine.set_xo_chip(false);
+        machine.reset(&mut screen);
+        assert_eq!(machine.pc(), 0x200);
+        assert!(machine.memory()[0x200..].iter().all(|&b| b == 0));
+
+        // A ROM that still fits survives the switch.
+        machine.set_xo_chip(true);
+        machine.load(&[0x00, 0xe0]).unwrap();
+        machine.set_xo_chip(false);
+        machine.reset(&mut screen);
+        assert_eq!(&machine.memory()[0x200..0x202], &[0x00, 0xe0]);
+    }
+
+    #[test]
+    fn test_set_font() {
+        let mut machine = Machine::new(Quirks::default());
+        let font = [0xaa; 80];
+        machine.set_font(&font);
+        assert_eq!(&machine.memory()[FONT_START..FONT_START + 80], &font[..]);
+        // The large digits are left alone.
+        assert_eq!(machine.memory()[BIG_FONT_START], 0xff);
+    }
+
+    #[test]
+    fn test_sys_is_ignored() {
+        // 0123 - SYS 0x123
+        let m = MachineBuilder::new().program(&[0x01, 0x23]).run(1);
+        assert_eq!(m.pc(), 0x202);
+    }
+}
@@ -1,109 +1,764 @@
-mod instructions;
-mod keyboard;
-mod machine;
-mod screen;
-
-use crate::keyboard::Keyboard;
-use crate::machine::Machine;
-use crate::screen::Screen;
-use piston_window::types::Color;
-use piston_window::*;
-use std::fs::read;
-use std::time::{Duration, Instant};
-
-const BACK_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
-const RATIO: f64 = 20.0;
-const EMULATOR_RATE: u64 = 1851; //540 Hz
+mod config;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+mod hud;
+mod keymap;
+mod keypad;
+mod piston_renderer;
+
+use crate::config::Config;
+use crate::keymap::Pulses;
+use crate::piston_renderer::PistonRenderer;
+use chip8::analysis::{detect_variant, Chip8Variant, Label};
+use chip8::ips;
+use chip8::keyboard::Keyboard;
+use chip8::machine::{
+    Machine, MachineError, StepOutcome, TimingMode, WatchKind, RAM_SIZE, XO_RAM_SIZE,
+};
+use chip8::palette::Palette;
+use chip8::recorder::GifRecorder;
+use chip8::render::{Renderer, TerminalRenderer};
+use chip8::replay::{InputLog, InputPlayer, InputRecorder};
+use chip8::roms::BuiltinRom;
+use chip8::screen::Screen;
+use chip8::sound::{NullSound, Sound};
+use piston_window::{
+    Button, Event, FileDrag, FocusEvent, Input, Key, MouseButton, MouseCursorEvent, PressEvent,
+    ReleaseEvent, RenderEvent, UpdateEvent,
+};
+use std::env;
+use std::fs::{read, write};
+use std::path::Path;
+use std::process::exit;
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// How much faster everything runs while the turbo key is held.
+const TURBO: u32 = 8;
+// Ten seconds of frames at 60 Hz to rewind through.
+const REWIND_FRAMES: usize = 60 * 10;
+// One minute of frames at 60 Hz.
+const MAX_RECORDING_FRAMES: usize = 60 * 60;
+// How far stepping over a call runs before giving up on it returning.
+const STEP_OVER_LIMIT: usize = 1_000_000;
 
 fn main() {
-    let (width, height) = (64, 32);
+    let mut disassemble = false;
+    let mut quirk_test = false;
+    let mut trace = false;
+    let mut profile = false;
+    let mut quiet = false;
+    let mut xo_chip = false;
+    let mut terminal = false;
+    let mut turbo = TURBO;
+    let mut turbo_timers = false;
+    let mut theme = None;
+    let mut breakpoints = Vec::new();
+    let mut watchpoints = Vec::new();
+    let mut config_path = None;
+    let mut record_input = None;
+    let mut replay_input = None;
+    let mut path = None;
+    let mut builtin = None;
+    let mut load_at = None;
+    let mut patch = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--disassemble" => disassemble = true,
+            "--quirk-test" => quirk_test = true,
+            "--trace" => trace = true,
+            "--profile" => profile = true,
+            "--quiet" => quiet = true,
+            "--xo-chip" => xo_chip = true,
+            "--terminal" => terminal = true,
+            "--turbo" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => turbo = n,
+                _ => {
+                    eprintln!("--turbo expects a speed multiplier, e.g. --turbo 8");
+                    exit(2);
+                }
+            },
+            "--turbo-timers" => turbo_timers = true,
+            "--theme" => match args.next().as_deref().and_then(Palette::named) {
+                Some(palette) => theme = Some(palette),
+                None => {
+                    eprintln!("--theme expects one of classic, green, amber or mono");
+                    exit(2);
+                }
+            },
+            "--builtin" => match args.next().as_deref().and_then(BuiltinRom::from_name) {
+                Some(rom) => builtin = Some(rom),
+                None => {
+                    let names: Vec<_> = BuiltinRom::ALL.iter().map(|rom| rom.name()).collect();
+                    eprintln!("--builtin expects one of {}", names.join(", "));
+                    exit(2);
+                }
+            },
+            "--load-at" => match args.next().as_deref().and_then(parse_addr) {
+                Some(addr) => load_at = Some(addr),
+                None => {
+                    eprintln!("--load-at expects a hex address, e.g. --load-at 0x600");
+                    exit(2);
+                }
+            },
+            "--break" => match args.next().as_deref().and_then(parse_addr) {
+                Some(addr) => breakpoints.push(addr),
+                None => {
+                    eprintln!("--break expects a hex address, e.g. --break 0x2a0");
+                    exit(2);
+                }
+            },
+            "--watch" => match args.next().as_deref().and_then(parse_addr) {
+                Some(addr) => watchpoints.push(addr),
+                None => {
+                    eprintln!("--watch expects a hex address, e.g. --watch 0x300");
+                    exit(2);
+                }
+            },
+            "--record-input" => match args.next() {
+                Some(path) => record_input = Some(path),
+                None => {
+                    eprintln!("--record-input expects a path, e.g. --record-input run.txt");
+                    exit(2);
+                }
+            },
+            "--replay-input" => match args.next() {
+                Some(path) => replay_input = Some(path),
+                None => {
+                    eprintln!("--replay-input expects a path, e.g. --replay-input run.txt");
+                    exit(2);
+                }
+            },
+            "--patch" => match args.next() {
+                Some(path) => patch = Some(path),
+                None => {
+                    eprintln!("--patch expects an IPS file, e.g. --patch hack.ips");
+                    exit(2);
+                }
+            },
+            "--config" => match args.next() {
+                Some(path) => config_path = Some(path),
+                None => {
+                    eprintln!("--config expects a path, e.g. --config chip8.toml");
+                    exit(2);
+                }
+            },
+            _ => path = Some(arg),
+        }
+    }
+
+    let path = match (path, builtin) {
+        (_, Some(rom)) => rom.name().to_string(),
+        (Some(path), None) => path,
+        (None, None) => {
+            eprintln!("usage: chip8 [--config <path>] [--disassemble] [--quirk-test] [--trace] [--profile] [--quiet] [--xo-chip] [--terminal] [--theme <name>] [--turbo <n>] [--turbo-timers] [--load-at <addr>] [--patch <path.ips>] [--break <addr>]... [--watch <addr>]... [--record-input <path> | --replay-input <path>] <rom.ch8 | --builtin <name>>");
+            exit(2);
+        }
+    };
+
+    let config = match Config::load(
+        Path::new(config_path.as_deref().unwrap_or(config::DEFAULT_PATH)),
+        config_path.is_some(),
+    ) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("could not read the config: {e}");
+            exit(1);
+        }
+    };
+    let palette = theme.unwrap_or_else(|| config.palette());
+
+    let f = match builtin.map_or_else(|| read(&path), |rom| Ok(rom.bytes().to_vec())) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("could not read {path}: {e}");
+            exit(1);
+        }
+    };
+    // Patched before anything looks at the ROM, so profiles and detection see the hack.
+    let f = match patch {
+        Some(patch) => match read(&patch).and_then(|p| ips::apply(&f, &p)) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("could not apply {patch}: {e}");
+                exit(1);
+            }
+        },
+        None => f,
+    };
+
+    let mut machine = Machine::new(config.quirks);
+    machine.set_xo_chip(xo_chip || config.xo_chip || detects_xo_chip(&f, quiet));
+    // Quirk profiles are for ordinary 0x200 ROMs, so a custom load address skips them.
+    let loaded = match load_at {
+        Some(addr) => machine.load_at(&f, addr),
+        None => machine.load_with_profiles(&f, &config.profiles()).map(drop),
+    };
+    if let Err(e) = loaded {
+        eprintln!("could not load {path}: {e}");
+        exit(1);
+    }
+
+    if disassemble {
+        let analysis = machine.analyze();
+        for (addr, asm) in machine.disassemble(machine.entry_point(), f.len().div_ceil(2)) {
+            match analysis.label(addr) {
+                Some(Label::Subroutine) => println!("\nsub_{addr:03X}:"),
+                Some(Label::Code) => println!("label_{addr:03X}:"),
+                None => {}
+            }
+            if analysis.is_code(addr) {
+                println!("{addr:03X}: {asm}");
+            } else {
+                println!("{addr:03X}: {asm}  ; unreachable");
+            }
+        }
+        return;
+    }
+
+    // Checks the quirks the ROM would run with, profiles included.
+    if quirk_test {
+        let report = machine.run_quirk_test();
+        print!("{report}");
+        exit(if report.passed() { 0 } else { 1 });
+    }
+
+    machine.set_clock_hz(config.clock_hz);
+    machine.set_cost_model(config.cost);
+    machine.set_strict_alignment(config.strict_alignment);
+    machine.set_wrap_memory(config.wrap_memory);
+    if config.protect_font {
+        machine.protect_font();
+    }
+    for &[start, end] in &config.write_protect {
+        machine.protect(start..end);
+    }
+    // Input logs are keyed by cycle, so the timers have to follow cycles too for a replay to
+    // see the same timer values the recording did.
+    if record_input.is_some() || replay_input.is_some() {
+        let cycles_per_frame = (config.clock_hz / 60).max(1);
+        machine.set_timing_mode(TimingMode::Lockstep { cycles_per_frame });
+    }
+    machine.set_rewind_limit(REWIND_FRAMES);
+    machine.set_sound(sound_backend());
+    if trace {
+        machine.set_trace_hook(Box::new(|pc, opcode, ins| {
+            eprintln!("{pc:03X}: {opcode:04X}  {ins}");
+        }));
+    }
+    machine.set_opcode_counts(profile);
+    for addr in breakpoints {
+        machine.add_breakpoint(addr);
+    }
+    for addr in watchpoints {
+        machine.add_watchpoint(addr, WatchKind::ReadWrite);
+    }
+
+    let mut input_player = replay_input.map(|path| match InputLog::load(Path::new(&path)) {
+        Ok(log) => InputPlayer::new(log),
+        Err(e) => {
+            eprintln!("could not read {path}: {e}");
+            exit(1);
+        }
+    });
+    let mut input_recorder = record_input
+        .as_ref()
+        .map(|_| InputRecorder::new(timestamp()));
+    if let Some(seed) = input_player
+        .as_ref()
+        .map(InputPlayer::seed)
+        .or(input_recorder.as_ref().map(|r| r.log().seed))
+    {
+        machine.seed_rng(seed);
+    }
+
+    let mut flags_path = format!("{path}.flags");
+    let mut saved_flags = load_flags(&mut machine, &flags_path);
+
+    if terminal {
+        run_terminal(&mut machine, quiet);
+        save_flags(&machine, &flags_path, saved_flags);
+        print_profile(&machine);
+        return;
+    }
+
+    let title = file_name(Path::new(&path));
 
-    let mut window: PistonWindow = WindowSettings::new(
-        "CMSC388Z Snake Game",
-        [
-            ((width as f64) * RATIO) as u32,
-            ((height as f64) * RATIO) as u32,
-        ],
-    )
-    .exit_on_esc(true)
-    .build()
-    .unwrap();
+    let (width, height) = (64, 32);
+    let mut scale = config.scale;
+    let mut show_keypad = config.show_keypad;
 
-    let f = read("chipquarium.ch8").expect("file not found");
+    let mut renderer = match PistonRenderer::new(
+        format!("CHIP-8 - {title}"),
+        window_width(width, height, show_keypad) * scale,
+        height * scale,
+        palette.clone(),
+    ) {
+        Ok(renderer) => renderer,
+        Err(e) => {
+            eprintln!("could not open a window: {e}");
+            exit(1);
+        }
+    };
 
     let mut screen = Screen::new();
     let mut keyboard = Keyboard::new();
-    let mut machine = Machine::new();
+    let keymap = config.keymap();
+    let mut pulses = Pulses::default();
+    #[cfg(feature = "gamepad")]
+    let mut gamepad = match gamepad::GamepadInput::new(gamepad::ButtonMap::default()) {
+        Ok(gamepad) => Some(gamepad),
+        Err(e) => {
+            eprintln!("gamepads disabled: {e}");
+            None
+        }
+    };
 
-    machine.load(&f);
+    let mut quick_save = None;
+    let mut recording: Option<GifRecorder> = None;
+    // Cycles owed to the CPU, carried between updates so fractions of a cycle and the extra
+    // cycles of costly instructions even out.
+    let mut cycle_budget = 0.0;
+    let mut speed = 1;
+    let mut rewinding = false;
+    let mut show_hud = false;
+    let mut cursor = [0.0; 2];
+    // The on-screen key held down with the mouse.
+    let mut clicked_key = None;
+    // Set when losing focus paused the machine, so only that pause is undone on regaining it.
+    let mut paused_in_background = false;
 
-    let mut last_tick = Instant::now();
+    while let Some(event) = renderer.next_event() {
+        #[cfg(feature = "gamepad")]
+        if let Some(gamepad) = &mut gamepad {
+            gamepad.poll(&mut keyboard);
+        }
+
+        match event.focus_args() {
+            Some(false) if config.pause_in_background && !machine.is_paused() => {
+                machine.pause();
+                paused_in_background = true;
+            }
+            Some(true) if paused_in_background => {
+                machine.resume();
+                paused_in_background = false;
+            }
+            _ => {}
+        }
 
-    while let Some(event) = window.next() {
-        if last_tick.elapsed() >= Duration::from_nanos(EMULATOR_RATE) {
-            machine.step(&keyboard, &mut screen);
-            last_tick = Instant::now();
+        // Updates run the CPU for the time they cover, render events only draw.
+        let dt = event.update_args().map(|args| args.dt);
+        if dt.is_some() && !rewinding {
+            machine.update_timers();
+        }
+        if machine.is_paused() || rewinding {
+            cycle_budget = 0.0;
+        } else if let Some(dt) = dt {
+            let clock_hz = 1.0 / machine.step_interval().as_secs_f64();
+            cycle_budget += dt * clock_hz * speed as f64;
+        }
+        while !machine.is_paused() && cycle_budget >= 1.0 {
+            if let Some(player) = &mut input_player {
+                player.apply(machine.cycles(), &mut keyboard);
+            }
+            if let Some(recorder) = &mut input_recorder {
+                recorder.record(machine.cycles(), &keyboard);
+            }
+            match machine.step(&keyboard, &mut screen) {
+                Ok(StepOutcome::Ok | StepOutcome::Paused | StepOutcome::Halted(_)) => {}
+                Ok(StepOutcome::BreakpointHit(addr)) => {
+                    println!(
+                        "breakpoint at {addr:#05x}, press P to resume, N to step or O to step over"
+                    );
+                    machine.pause();
+                }
+                Ok(StepOutcome::WatchpointHit(addr, access)) => {
+                    println!(
+                        "{access:?} of {addr:#05x}, press P to resume, N to step or O to step over"
+                    );
+                    machine.pause();
+                }
+                Err(e) if is_fetch_fault(&machine, &e) => {
+                    println!("{e}, paused");
+                    machine.pause();
+                }
+                Err(e) if !quiet => eprintln!("{e}"),
+                Err(_) => {}
+            }
+            cycle_budget -= machine.last_cost() as f64;
+        }
+
+        if let Event::Input(Input::FileDrag(FileDrag::Drop(dropped)), _) = &event {
+            let entry_point = machine.entry_point();
+            let dropped_rom = read_dropped_rom(dropped).and_then(|rom| {
+                // The ROM picks the memory layout, so it has to fit in that layout's RAM.
+                let xo = xo_chip || config.xo_chip || detects_xo_chip(&rom, quiet);
+                let ram_size = if xo { XO_RAM_SIZE } else { RAM_SIZE };
+                let max_size = ram_size.saturating_sub(entry_point);
+                if rom.len() > max_size {
+                    return Err(format!(
+                        "{} bytes, a ROM has to be between 1 and {max_size}",
+                        rom.len()
+                    ));
+                }
+                Ok((rom, xo))
+            });
+            match dropped_rom {
+                Ok((rom, xo)) => {
+                    save_flags(&machine, &flags_path, saved_flags);
+                    machine.set_quirks(config.profiles().get(&rom).unwrap_or(config.quirks));
+                    machine.set_xo_chip(xo);
+                    match machine.load_at(&rom, entry_point) {
+                        Ok(()) => {
+                            // Wipe whatever the last ROM left past the end of this one.
+                            machine.reset(&mut screen);
+                            renderer.set_title(format!("CHIP-8 - {}", file_name(dropped)));
+                            flags_path = format!("{}.flags", dropped.display());
+                            saved_flags = load_flags(&mut machine, &flags_path);
+                            quick_save = None;
+                            cycle_budget = 0.0;
+                        }
+                        Err(e) => eprintln!("could not load {}: {e}", dropped.display()),
+                    }
+                }
+                Err(e) => eprintln!("could not load {}: {e}", dropped.display()),
+            }
         }
 
         if let Some(Button::Keyboard(key)) = event.press_args() {
+            // A replay owns the keypad.
+            if input_player.is_none() {
+                if keymap.is_pulse(key) {
+                    pulses.press(&mut keyboard, keymap.get(key));
+                } else {
+                    for &k in keymap.get(key) {
+                        keyboard.press(k as usize);
+                    }
+                }
+            }
+
             match key {
-                Key::Up => {
-                    keyboard.press(1);
-                    keyboard.press(2);
-                },
-                Key::Down => {
-                    keyboard.press(4);
-                    keyboard.press(8);
+                Key::Tab => {
+                    speed = turbo;
+                    if turbo_timers {
+                        machine.set_timer_speed(turbo);
+                    }
+                }
+                Key::Backspace => rewinding = true,
+                Key::P => {
+                    if machine.is_paused() {
+                        machine.resume();
+                    } else {
+                        machine.pause();
+                    }
+                }
+                Key::N => {
+                    if let Some(recorder) = &mut input_recorder {
+                        recorder.record(machine.cycles(), &keyboard);
+                    }
+                    if let Err(e) = machine.step_once(&keyboard, &mut screen) {
+                        eprintln!("{e}");
+                    }
+                }
+                Key::O => {
+                    if let Some(recorder) = &mut input_recorder {
+                        recorder.record(machine.cycles(), &keyboard);
+                    }
+                    match machine.step_over(STEP_OVER_LIMIT, &keyboard, &mut screen) {
+                        Ok(Some(StepOutcome::BreakpointHit(addr))) => {
+                            println!("breakpoint at {addr:#05x}")
+                        }
+                        Ok(Some(StepOutcome::WatchpointHit(addr, access))) => {
+                            println!("{access:?} of {addr:#05x}")
+                        }
+                        Ok(Some(_)) => {}
+                        Ok(None) => println!("the call didn't return in {STEP_OVER_LIMIT} steps"),
+                        Err(e) => eprintln!("{e}"),
+                    }
+                }
+                Key::F1 => show_hud = !show_hud,
+                Key::F2 => {
+                    let enabled = !renderer.collision_overlay();
+                    renderer.set_collision_overlay(enabled);
+                }
+                Key::F3 => {
+                    machine.reset(&mut screen);
+                    cycle_budget = 0.0;
+                }
+                Key::F5 => quick_save = Some(machine.save_state(&screen)),
+                Key::F9 => {
+                    if let Some(state) = &quick_save {
+                        if let Err(e) = machine.load_state(state.clone(), &mut screen) {
+                            eprintln!("could not load the quick save: {e}");
+                        }
+                    }
+                }
+                Key::F10 => renderer.toggle_fullscreen(),
+                Key::F11 => match recording.take() {
+                    Some(recorder) => save_recording(&recorder),
+                    None => {
+                        println!("recording, press F11 again to stop");
+                        recording = Some(GifRecorder::new(scale, MAX_RECORDING_FRAMES));
+                    }
                 },
-                Key::W => keyboard.press(11),
-                Key::A => keyboard.press(12),
-                Key::S => keyboard.press(13),
-                Key::D => keyboard.press(14),
-                Key::Space => keyboard.press(15),
+                Key::F12 => screenshot(&screen, scale, &palette),
+                Key::Equals | Key::Minus => {
+                    scale = if key == Key::Equals {
+                        scale + 1
+                    } else {
+                        (scale - 1).max(1)
+                    };
+                    renderer.set_size(
+                        window_width(width, height, show_keypad) * scale,
+                        height * scale,
+                    );
+                }
+                Key::F4 => {
+                    show_keypad = !show_keypad;
+                    renderer.set_size(
+                        window_width(width, height, show_keypad) * scale,
+                        height * scale,
+                    );
+                }
                 _ => {}
             }
         }
 
+        if let Some(pos) = event.mouse_cursor_args() {
+            cursor = pos;
+        }
+        if let Some(Button::Mouse(MouseButton::Left)) = event.press_args() {
+            clicked_key = renderer.keypad_key_at(cursor);
+            if let (Some(key), None) = (clicked_key, &input_player) {
+                keyboard.press(key as usize);
+            }
+        }
+        if let Some(Button::Mouse(MouseButton::Left)) = event.release_args() {
+            if let (Some(key), None) = (clicked_key.take(), &input_player) {
+                keyboard.release(key as usize);
+            }
+        }
+
         if let Some(Button::Keyboard(key)) = event.release_args() {
-            match key {
-                Key::Up => {
-                    keyboard.release(1);
-                    keyboard.release(2);
-                },
-                Key::Down => {
-                    keyboard.release(4);
-                    keyboard.release(8);
-                },
-                Key::W => keyboard.release(11),
-                Key::A => keyboard.release(12),
-                Key::S => keyboard.release(13),
-                Key::D => keyboard.release(14),
-                Key::Space => keyboard.release(15),
-                _ => {}
+            if input_player.is_none() && !keymap.is_pulse(key) {
+                for &k in keymap.get(key) {
+                    keyboard.release(k as usize);
+                }
+            }
+
+            if key == Key::Backspace {
+                rewinding = false;
+            }
+            if key == Key::Tab {
+                speed = 1;
+                machine.set_timer_speed(1);
             }
         }
 
-        window.draw_2d(&event, |c, g, _| {
-            clear(BACK_COLOR, g);
-            for i in 0..32 {
-                for j in 0..64 {
-                    match screen.get(j, i) {
-                        x if x > 0 => {
-                            let x = x as f32 / 100.0;
-                            let clr: Color = [x, x, x, 1.0];
-                            draw_block(clr, j as i32, i as i32, &c, g);
-                        }
-                        _ => {}
-                    }
+        if event.render_args().is_some() {
+            if input_player.is_none() {
+                pulses.frame(&mut keyboard);
+            }
+            renderer.set_keypad(show_keypad.then(|| keyboard.keys()));
+            renderer.set_hud(if show_hud {
+                hud::lines(&machine)
+            } else {
+                Vec::new()
+            });
+        }
+        renderer.draw(&screen);
+        if event.render_args().is_some() {
+            if rewinding {
+                machine.rewind(&mut screen);
+            } else if !machine.is_paused() {
+                machine.record_history(&screen);
+            }
+            if let Some(recorder) = &mut recording {
+                recorder.draw(&screen);
+                if recorder.is_full() {
+                    save_recording(recorder);
+                    recording = None;
+                }
+            }
+            screen.decay();
+            keyboard.tick();
+        }
+    }
+
+    // The window is closed, by Esc or its close button alike, so save whatever would be lost.
+    save_flags(&machine, &flags_path, saved_flags);
+    if let Some(recorder) = recording.filter(|recorder| !recorder.is_empty()) {
+        save_recording(&recorder);
+    }
+    print_profile(&machine);
+    if let (Some(recorder), Some(path)) = (input_recorder, record_input) {
+        match recorder.finish().save(Path::new(&path)) {
+            Ok(()) => println!("saved input recording to {path}"),
+            Err(e) => eprintln!("could not save {path}: {e}"),
+        }
+    }
+}
+
+/// The window's width in CHIP-8 pixels for a `width` x `height` display, with room for the
+/// square keypad beside it when it's shown.
+fn window_width(width: u32, height: u32, show_keypad: bool) -> u32 {
+    if show_keypad {
+        width + height
+    } else {
+        width
+    }
+}
+
+/// Lists the instructions `--profile` counted, most executed first. Prints nothing when
+/// counting is off.
+fn print_profile(machine: &Machine) {
+    let mut counts: Vec<_> = machine.opcode_counts().into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (pattern, count) in counts {
+        println!("{pattern}  {count}");
+    }
+}
+
+/// Runs the ROM drawing to the terminal instead of a window. There's no keypad input this way.
+fn run_terminal(machine: &mut Machine, quiet: bool) {
+    let mut screen = Screen::new();
+    let keyboard = Keyboard::new();
+    let mut renderer = TerminalRenderer::stdout();
+    let frame = Duration::from_micros(16_666);
+    let mut last_tick = Instant::now();
+
+    loop {
+        let step_interval = machine.step_interval();
+        while last_tick.elapsed() >= step_interval {
+            match machine.step(&keyboard, &mut screen) {
+                Ok(StepOutcome::Ok | StepOutcome::Paused | StepOutcome::Halted(_)) => {}
+                Ok(StepOutcome::BreakpointHit(addr)) => {
+                    println!("breakpoint at {addr:#05x}, stopping");
+                    return;
                 }
+                Ok(StepOutcome::WatchpointHit(addr, access)) => {
+                    println!("{access:?} of {addr:#05x}, stopping");
+                    return;
+                }
+                Err(e) if is_fetch_fault(machine, &e) => {
+                    println!("{e}, stopping");
+                    return;
+                }
+                Err(e) if !quiet => eprintln!("{e}"),
+                Err(_) => {}
             }
-        });
+            last_tick += step_interval * machine.last_cost();
+        }
+
+        renderer.draw(&screen);
+        screen.clear_dirty();
+        sleep(frame);
+    }
+}
+
+/// Whether `e` is the machine failing to fetch the instruction at the PC. The PC doesn't move
+/// for those, so stepping on would only report the same fault every cycle.
+fn is_fetch_fault(machine: &Machine, e: &MachineError) -> bool {
+    let pc = machine.pc();
+    matches!(*e, MachineError::OutOfBounds { addr } if addr == pc + 1 || addr == pc + 3)
+}
+
+/// Whether `rom` uses XO-CHIP instructions, in which case the machine needs XO-CHIP mode to
+/// run it. Other variants need no setup, SUPER-CHIP ROMs switch resolution themselves.
+fn detects_xo_chip(rom: &[u8], quiet: bool) -> bool {
+    let xo_chip = detect_variant(rom) == Chip8Variant::XoChip;
+    if xo_chip && !quiet {
+        println!("XO-CHIP instructions found, running in XO-CHIP mode");
+    }
+    xo_chip
+}
+
+/// Reads a ROM dropped onto the window, refusing anything that doesn't look like one.
+fn read_dropped_rom(path: &Path) -> Result<Vec<u8>, String> {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    if !matches!(extension.as_deref(), Some("ch8" | "c8" | "sc8" | "xo8")) {
+        return Err("not a CHIP-8 ROM, expected a .ch8, .c8, .sc8 or .xo8 file".to_string());
+    }
+
+    let rom = read(path).map_err(|e| e.to_string())?;
+    if rom.is_empty() {
+        return Err("empty file".to_string());
     }
+    Ok(rom)
 }
 
-pub fn draw_block(color: Color, x: i32, y: i32, con: &Context, g: &mut G2d) {
-    let gui_x = (x as f64) * RATIO;
-    let gui_y = (y as f64) * RATIO;
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Restores the SUPER-CHIP flags saved by an earlier run, returning what's now in the machine.
+fn load_flags(machine: &mut Machine, path: &str) -> [u8; 8] {
+    if let Ok(flags) = read(path) {
+        if let Ok(flags) = flags.try_into() {
+            machine.set_rpl_flags(flags);
+        }
+    }
+    machine.rpl_flags()
+}
+
+/// Keeps the SUPER-CHIP flags, usually a high score, for the next run of the same ROM.
+fn save_flags(machine: &Machine, path: &str, saved: [u8; 8]) {
+    let flags = machine.rpl_flags();
+    if flags == saved {
+        return;
+    }
+
+    if let Err(e) = write(path, flags) {
+        eprintln!("could not save {path}: {e}");
+    }
+}
+
+fn screenshot(screen: &Screen, scale: u32, palette: &Palette) {
+    let path = timestamped("png");
+
+    match screen.to_png(Path::new(&path), scale, palette) {
+        Ok(()) => println!("saved screenshot to {path}"),
+        Err(e) => eprintln!("could not save {path}: {e}"),
+    }
+}
+
+fn save_recording(recorder: &GifRecorder) {
+    let path = timestamped("gif");
+
+    match recorder.save(Path::new(&path)) {
+        Ok(()) => println!("saved {} frames to {path}", recorder.len()),
+        Err(e) => eprintln!("could not save {path}: {e}"),
+    }
+}
+
+/// A file name for a capture taken now, like `chip8-1700000000.png`.
+fn timestamped(extension: &str) -> String {
+    format!("chip8-{}.{extension}", timestamp())
+}
+
+/// Seconds since the Unix epoch.
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn parse_addr(s: &str) -> Option<usize> {
+    usize::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
+
+fn sound_backend() -> Box<dyn Sound> {
+    #[cfg(feature = "audio")]
+    match chip8::sound::Beeper::new() {
+        Ok(beeper) => return Box::new(beeper),
+        Err(e) => eprintln!("audio disabled: {e}"),
+    }
 
-    rectangle(color, [gui_x, gui_y, RATIO, RATIO], con.transform, g);
+    Box::new(NullSound)
 }
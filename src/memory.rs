@@ -0,0 +1,46 @@
+use std::ops::DerefMut;
+
+/// The RAM behind a `Machine`.
+///
+/// Every access an instruction makes, fetches included, goes through `read` and `write`, so
+/// an implementation can map registers or devices over some addresses. Bulk access, like
+/// loading ROMs, save states and `Machine::memory`, sees the plain bytes through the slice.
+///
+/// `Vec<u8>` is the plain RAM every `Machine::new` uses.
+pub trait Memory: DerefMut<Target = [u8]> {
+    /// Changes the size to `len` bytes, zero filling any new ones.
+    fn resize(&mut self, len: usize);
+
+    #[inline]
+    fn read(&mut self, addr: usize) -> u8 {
+        self[addr]
+    }
+
+    #[inline]
+    fn write(&mut self, addr: usize, value: u8) {
+        self[addr] = value;
+    }
+}
+
+impl Memory for Vec<u8> {
+    fn resize(&mut self, len: usize) {
+        Vec::resize(self, len, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::Memory;
+
+    #[test]
+    fn test_vec_memory() {
+        let mut ram = vec![0; 16];
+        ram.write(3, 0x42);
+        assert_eq!(ram.read(3), 0x42);
+
+        Memory::resize(&mut ram, 32);
+        assert_eq!(ram.len(), 32);
+        assert_eq!(ram[3], 0x42);
+        assert_eq!(ram[31], 0);
+    }
+}
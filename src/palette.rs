@@ -0,0 +1,136 @@
+use crate::screen::{Screen, FULL_BRIGHTNESS};
+
+/// An RGBA color with components from 0.0 to 1.0.
+pub type Color = [f32; 4];
+
+/// The colors the display is drawn with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub background: Color,
+    /// Pixels lit in the first plane only, which is every lit pixel outside XO-CHIP.
+    pub foreground: Color,
+    /// XO-CHIP pixels lit in other combinations of planes, indexed by the plane mask minus 2.
+    /// Masks without a color here use `foreground`.
+    pub planes: Vec<Color>,
+}
+
+impl Palette {
+    /// White on dark gray, what the emulator has always looked like.
+    pub fn classic() -> Self {
+        Palette {
+            background: [0.2, 0.2, 0.2, 1.0],
+            foreground: [1.0, 1.0, 1.0, 1.0],
+            planes: vec![[0.6, 0.6, 0.6, 1.0], [0.4, 0.4, 0.4, 1.0]],
+        }
+    }
+
+    /// Green phosphor.
+    pub fn green() -> Self {
+        Palette {
+            background: [0.0, 0.08, 0.0, 1.0],
+            foreground: [0.2, 1.0, 0.2, 1.0],
+            planes: vec![[0.1, 0.6, 0.1, 1.0], [0.6, 1.0, 0.6, 1.0]],
+        }
+    }
+
+    /// Amber phosphor.
+    pub fn amber() -> Self {
+        Palette {
+            background: [0.08, 0.04, 0.0, 1.0],
+            foreground: [1.0, 0.7, 0.0, 1.0],
+            planes: vec![[0.6, 0.4, 0.0, 1.0], [1.0, 0.85, 0.5, 1.0]],
+        }
+    }
+
+    pub fn black_and_white() -> Self {
+        Palette {
+            background: [0.0, 0.0, 0.0, 1.0],
+            foreground: [1.0, 1.0, 1.0, 1.0],
+            planes: vec![[0.67, 0.67, 0.67, 1.0], [0.33, 0.33, 0.33, 1.0]],
+        }
+    }
+
+    /// Looks up a built-in palette: `classic`, `green`, `amber` or `mono`.
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(Palette::classic()),
+            "green" => Some(Palette::green()),
+            "amber" => Some(Palette::amber()),
+            "mono" => Some(Palette::black_and_white()),
+            _ => None,
+        }
+    }
+
+    /// The color for a pixel lit in the planes in `mask`.
+    pub fn color(&self, mask: u8) -> Color {
+        match mask {
+            0 => self.background,
+            1 => self.foreground,
+            n => self
+                .planes
+                .get(n as usize - 2)
+                .copied()
+                .unwrap_or(self.foreground),
+        }
+    }
+
+    /// The color a pixel is displayed in, switched off pixels fade from the foreground to the
+    /// background.
+    pub fn pixel(&self, screen: &Screen, x: usize, y: usize) -> Color {
+        let planes = screen.planes(x, y);
+        if planes != 0 {
+            return self.color(planes);
+        }
+
+        let t = screen.get(x, y) as f32 / FULL_BRIGHTNESS as f32;
+        let mut color = self.background;
+        for (c, fg) in color.iter_mut().zip(self.foreground) {
+            *c += (fg - *c) * t;
+        }
+        color
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::classic()
+    }
+}
+
+/// Converts to 8-bit RGB, dropping the alpha.
+pub fn to_rgb8(color: Color) -> [u8; 3] {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [channel(color[0]), channel(color[1]), channel(color[2])]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::palette::{to_rgb8, Palette};
+    use crate::screen::Screen;
+
+    #[test]
+    fn test_palette() {
+        let palette = Palette::named("green").unwrap();
+        assert!(Palette::named("purple").is_none());
+
+        let mut screen = Screen::new();
+        screen.set(0, 0, 1);
+        screen.select_planes(0b10);
+        screen.set(1, 0, 1);
+
+        assert_eq!(palette.pixel(&screen, 0, 0), palette.foreground);
+        assert_eq!(palette.pixel(&screen, 1, 0), palette.planes[0]);
+        assert_eq!(palette.pixel(&screen, 2, 0), palette.background);
+        assert_eq!(palette.color(0b1111), palette.foreground);
+
+        // A pixel that was just switched off still shows the foreground, then fades.
+        screen.select_planes(1);
+        screen.set(0, 0, 0);
+        assert_eq!(palette.pixel(&screen, 0, 0), palette.foreground);
+        screen.decay();
+        let faded = palette.pixel(&screen, 0, 0);
+        assert!(faded[1] < palette.foreground[1] && faded[1] > palette.background[1]);
+
+        assert_eq!(to_rgb8([1.0, 0.0, 0.5, 1.0]), [255, 0, 128]);
+    }
+}
@@ -0,0 +1,235 @@
+use crate::hud::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::keypad::KeypadLayout;
+use chip8::palette::{Color, Palette};
+use chip8::render::Renderer;
+use chip8::screen::Screen;
+use glutin::window::Fullscreen;
+use piston_window::*;
+
+// The tint of pixels erased by the last sprite draw, when the collision overlay is on.
+const COLLISION: Color = [1.0, 0.0, 0.0, 1.0];
+// Around the display when the window's aspect ratio doesn't match it.
+const BARS: Color = [0.0, 0.0, 0.0, 1.0];
+const HUD_TEXT: Color = [1.0, 1.0, 0.4, 1.0];
+const HUD_BACKGROUND: Color = [0.0, 0.0, 0.0, 0.75];
+// Wake-ups per second for updates and for frames. The main loop runs the CPU for the time
+// each update covers and the event loop sleeps in between, so 60 keeps the batches in step
+// with the 60 Hz timers and display without waking up more than that needs.
+const UPDATES_PER_SECOND: u64 = 60;
+const FRAMES_PER_SECOND: u64 = 60;
+const KEY: Color = [0.3, 0.3, 0.3, 1.0];
+const KEY_PRESSED: Color = [0.8, 0.8, 0.8, 1.0];
+const KEY_LABEL: Color = [1.0, 1.0, 1.0, 1.0];
+const KEY_LABEL_PRESSED: Color = [0.0, 0.0, 0.0, 1.0];
+
+/// Draws into a piston window. The window also supplies the input events, so the main loop
+/// pulls them through `next_event` and `draw` renders on the render events among them.
+pub struct PistonRenderer {
+    window: PistonWindow,
+    event: Option<Event>,
+    palette: Palette,
+    show_collisions: bool,
+    hud: Vec<String>,
+    keypad: Option<[bool; 16]>,
+    keypad_layout: Option<KeypadLayout>,
+    fullscreen: bool,
+}
+
+impl PistonRenderer {
+    pub fn new(title: String, width: u32, height: u32, palette: Palette) -> Result<Self, String> {
+        let mut window: PistonWindow = WindowSettings::new(title, [width, height])
+            .exit_on_esc(true)
+            .build()
+            .map_err(|e| e.to_string())?;
+        window.set_ups(UPDATES_PER_SECOND);
+        window.set_max_fps(FRAMES_PER_SECOND);
+
+        Ok(PistonRenderer {
+            window,
+            event: None,
+            palette,
+            show_collisions: false,
+            hud: Vec::new(),
+            keypad: None,
+            keypad_layout: None,
+            fullscreen: false,
+        })
+    }
+
+    /// Highlights the pixels each sprite draw collided with, a debugging aid.
+    pub fn set_collision_overlay(&mut self, enabled: bool) {
+        self.show_collisions = enabled;
+    }
+
+    pub fn collision_overlay(&self) -> bool {
+        self.show_collisions
+    }
+
+    /// Text drawn over the top left of the game, nothing when empty.
+    pub fn set_hud(&mut self, lines: Vec<String>) {
+        self.hud = lines;
+    }
+
+    /// Shows the hex keypad to the right of the game, with the keys that are down in `pressed`
+    /// highlighted. `None` hides it.
+    pub fn set_keypad(&mut self, pressed: Option<[bool; 16]>) {
+        self.keypad = pressed;
+        if pressed.is_none() {
+            self.keypad_layout = None;
+        }
+    }
+
+    /// The on-screen key under the window position `pos`, as of the last draw.
+    pub fn keypad_key_at(&self, pos: [f64; 2]) -> Option<u8> {
+        self.keypad_layout?.key_at(pos)
+    }
+
+    pub fn set_title(&mut self, title: String) {
+        self.window.set_title(title);
+    }
+
+    /// Resizes the window to `width` x `height`, ignored while fullscreen.
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        if !self.fullscreen {
+            self.window.set_size([width, height]);
+        }
+    }
+
+    /// Switches between a window and borderless fullscreen on the current monitor.
+    pub fn toggle_fullscreen(&mut self) {
+        self.fullscreen = !self.fullscreen;
+        let fullscreen = self.fullscreen.then_some(Fullscreen::Borderless(None));
+        self.window.window.ctx.window().set_fullscreen(fullscreen);
+    }
+
+    /// Waits for the next window event, `None` once the window is closed.
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.event = self.window.next();
+        self.event.clone()
+    }
+}
+
+impl Renderer for PistonRenderer {
+    fn draw(&mut self, screen: &Screen) {
+        let event = match &self.event {
+            Some(event) => event,
+            None => return,
+        };
+
+        // Keep the pixels square whatever the window's shape, centring the display and leaving
+        // bars on the sides that don't fit. The keypad is a square as tall as the display.
+        let window = self.window.size();
+        let (width, height) = (screen.width() as f64, screen.height() as f64);
+        let keypad_width = if self.keypad.is_some() { height } else { 0.0 };
+        let size = (window.width / (width + keypad_width)).min(window.height / height);
+        let left = (window.width - (width + keypad_width) * size) / 2.0;
+        let top = (window.height - height * size) / 2.0;
+        self.keypad_layout = self.keypad.map(|_| KeypadLayout {
+            left: left + width * size,
+            top,
+            size: height * size,
+        });
+        let keypad = self.keypad.zip(self.keypad_layout);
+        let palette = &self.palette;
+        let collisions = if self.show_collisions {
+            screen.last_collisions()
+        } else {
+            &[]
+        };
+        let hud = &self.hud;
+        self.window.draw_2d(event, |context, g, _| {
+            clear(BARS, g);
+            let c = context.trans(left, top);
+            rectangle(
+                palette.background,
+                [0.0, 0.0, width * size, height * size],
+                c.transform,
+                g,
+            );
+            for (x, y, _) in screen.set_pixels() {
+                draw_block(palette.pixel(screen, x, y), x as i32, y as i32, size, &c, g);
+            }
+            for &(x, y) in collisions {
+                draw_block(COLLISION, x as i32, y as i32, size, &c, g);
+            }
+            draw_text(hud, (size / 8.0).max(1.0), &c, g);
+            if let Some((pressed, layout)) = keypad {
+                draw_keypad(&pressed, &layout, &context, g);
+            }
+        });
+    }
+}
+
+/// Draws the keypad at `layout`, which is in window coordinates, with each key's hex digit
+/// in the HUD font.
+fn draw_keypad(pressed: &[bool; 16], layout: &KeypadLayout, con: &Context, g: &mut G2d) {
+    for (key, rect) in layout.keys() {
+        let down = pressed[key as usize];
+        rectangle(if down { KEY_PRESSED } else { KEY }, rect, con.transform, g);
+
+        let [x, y, width, height] = rect;
+        let scale = (height / 2.0 / GLYPH_HEIGHT as f64).floor().max(1.0);
+        let left = x + (width - GLYPH_WIDTH as f64 * scale) / 2.0;
+        let top = y + (height - GLYPH_HEIGHT as f64 * scale) / 2.0;
+        let color = if down { KEY_LABEL_PRESSED } else { KEY_LABEL };
+        let digit = std::char::from_digit(key as u32, 16).unwrap_or(' ');
+        for (row, bits) in glyph(digit.to_ascii_uppercase()).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits >> (GLYPH_WIDTH - 1 - column) & 1 == 1 {
+                    let pixel = [
+                        left + column as f64 * scale,
+                        top + row as f64 * scale,
+                        scale,
+                        scale,
+                    ];
+                    rectangle(color, pixel, con.transform, g);
+                }
+            }
+        }
+    }
+}
+
+/// Draws `lines` in the HUD font with each font pixel `scale` screen pixels wide.
+fn draw_text(lines: &[String], scale: f64, con: &Context, g: &mut G2d) {
+    if lines.is_empty() {
+        return;
+    }
+
+    let advance = (GLYPH_WIDTH + 1) as f64 * scale;
+    let line_height = (GLYPH_HEIGHT + 2) as f64 * scale;
+    let columns = lines.iter().map(|l| l.len()).max().unwrap_or(0);
+    let background = [
+        0.0,
+        0.0,
+        columns as f64 * advance + scale * 3.0,
+        lines.len() as f64 * line_height + scale * 2.0,
+    ];
+    rectangle(HUD_BACKGROUND, background, con.transform, g);
+
+    for (row, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let left = scale * 2.0 + column as f64 * advance;
+            let top = scale * 2.0 + row as f64 * line_height;
+            for (y, bits) in glyph(c).iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if bits >> (GLYPH_WIDTH - 1 - x) & 1 == 1 {
+                        let pixel = [
+                            left + x as f64 * scale,
+                            top + y as f64 * scale,
+                            scale,
+                            scale,
+                        ];
+                        rectangle(HUD_TEXT, pixel, con.transform, g);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn draw_block(color: Color, x: i32, y: i32, size: f64, con: &Context, g: &mut G2d) {
+    let gui_x = (x as f64) * size;
+    let gui_y = (y as f64) * size;
+
+    rectangle(color, [gui_x, gui_y, size, size], con.transform, g);
+}
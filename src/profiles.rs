@@ -0,0 +1,110 @@
+use crate::quirks::Quirks;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// A SHA-256 digest identifying a ROM.
+pub type RomHash = [u8; 32];
+
+/// Known ROMs, by their SHA-256 in hex, and the quirks they need.
+const KNOWN: &[(&str, Quirks)] = &[
+    // The IBM logo demo, written for the COSMAC VIP.
+    (
+        "8bf3b46d8a64c2074e7538200f684a2eaced258404d3c7d3bd7a917c3d0143e5",
+        Quirks::cosmac_vip(),
+    ),
+];
+
+pub fn rom_hash(rom: &[u8]) -> RomHash {
+    Sha256::digest(rom).into()
+}
+
+/// Parses a hash written as 64 hex digits, the way `sha256sum` prints it.
+pub fn parse_hash(s: &str) -> Option<RomHash> {
+    if s.len() != 64 || !s.is_ascii() {
+        return None;
+    }
+
+    let mut hash = [0; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+/// A table of the quirks known ROMs need, so they run correctly without configuring anything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profiles {
+    quirks: HashMap<RomHash, Quirks>,
+}
+
+impl Profiles {
+    /// An empty table.
+    pub fn new() -> Self {
+        Profiles {
+            quirks: HashMap::new(),
+        }
+    }
+
+    /// Adds or replaces the profile for the ROM with `hash`.
+    pub fn insert(&mut self, hash: RomHash, quirks: Quirks) {
+        self.quirks.insert(hash, quirks);
+    }
+
+    /// The quirks `rom` needs, if it's in the table.
+    pub fn get(&self, rom: &[u8]) -> Option<Quirks> {
+        self.quirks.get(&rom_hash(rom)).copied()
+    }
+}
+
+impl Default for Profiles {
+    /// The profiles shipped with the emulator.
+    fn default() -> Self {
+        let mut profiles = Profiles::new();
+        for &(hash, quirks) in KNOWN {
+            profiles.insert(parse_hash(hash).expect("bad hash in KNOWN"), quirks);
+        }
+        profiles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::profiles::{parse_hash, rom_hash, Profiles};
+    use crate::quirks::Quirks;
+    use crate::roms::BuiltinRom;
+
+    #[test]
+    fn test_builtin_profiles() {
+        let profiles = Profiles::default();
+        assert_eq!(
+            profiles.get(BuiltinRom::IbmLogo.bytes()),
+            Some(Quirks::cosmac_vip())
+        );
+        assert_eq!(profiles.get(BuiltinRom::OpcodeTest.bytes()), None);
+    }
+
+    #[test]
+    fn test_insert() {
+        let rom = [0x12, 0x00];
+        let mut profiles = Profiles::new();
+        assert_eq!(profiles.get(&rom), None);
+
+        let quirks = Quirks {
+            jump_with_vx: true,
+            ..Quirks::default()
+        };
+        profiles.insert(rom_hash(&rom), quirks);
+        assert_eq!(profiles.get(&rom), Some(quirks));
+    }
+
+    #[test]
+    fn test_parse_hash() {
+        let hash = "8bf3b46d8a64c2074e7538200f684a2eaced258404d3c7d3bd7a917c3d0143e5";
+        assert_eq!(
+            parse_hash(hash),
+            Some(rom_hash(BuiltinRom::IbmLogo.bytes()))
+        );
+        assert_eq!(parse_hash(&hash[2..]), None);
+        assert_eq!(parse_hash(&hash.replace('8', "g")), None);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::keyboard::Keyboard;
+use crate::machine::Machine;
+use crate::memory::Memory;
+use crate::quirks::Quirks;
+use crate::roms::BuiltinRom;
+use crate::screen::Screen;
+use std::fmt::{Display, Formatter};
+
+/// More than the quirks ROM needs to reach its final self-jump.
+const MAX_STEPS: usize = 1000;
+
+/// A quirk's name, the bit the quirks ROM sets in V5 when it detects it and the field.
+type Probe = (&'static str, u8, fn(&Quirks) -> bool);
+
+/// Every behaviour the quirks ROM looks for, see `BuiltinRom::QuirksTest`.
+const PROBES: [Probe; 6] = [
+    ("vf_reset_on_logic", 1, |q| q.vf_reset_on_logic),
+    ("shift_uses_vy", 2, |q| q.shift_uses_vy),
+    ("load_store_increments_i", 4, |q| q.load_store_increments_i),
+    ("jump_with_vx", 8, |q| q.jump_with_vx),
+    ("clip_sprites", 16, |q| q.clip_sprites),
+    ("add_i_sets_vf", 32, |q| q.add_i_sets_vf),
+];
+
+/// How one quirk fared in `Machine::run_quirk_test`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuirkResult {
+    /// The field's name in `Quirks`.
+    pub name: &'static str,
+    /// Whether the config turns the quirk on.
+    pub configured: bool,
+    /// Whether the ROM saw the machine behave that way.
+    pub detected: bool,
+}
+
+impl QuirkResult {
+    pub fn passed(&self) -> bool {
+        self.configured == self.detected
+    }
+}
+
+/// Every quirk the self-test probes, in the order of the ROM's bits. `display_wait` isn't
+/// among them, it depends on the wall clock rather than on what the instructions do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuirkReport {
+    pub results: Vec<QuirkResult>,
+}
+
+impl QuirkReport {
+    /// Whether the machine behaved as configured for every quirk.
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(QuirkResult::passed)
+    }
+}
+
+impl Display for QuirkReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for result in &self.results {
+            let on_off = |b| if b { "on" } else { "off" };
+            writeln!(
+                f,
+                "{:<24} configured {:<3} detected {:<3} {}",
+                result.name,
+                on_off(result.configured),
+                on_off(result.detected),
+                if result.passed() { "pass" } else { "FAIL" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<M: Memory> Machine<M> {
+    /// Runs the bundled quirks ROM on a fresh machine with this machine's quirks and checks
+    /// that each behaviour it detects is the one the quirks ask for. This machine is left as
+    /// it is.
+    pub fn run_quirk_test(&self) -> QuirkReport {
+        let quirks = self.quirks();
+        let mut machine = Machine::new(Quirks {
+            display_wait: false,
+            ..quirks
+        });
+        let mut screen = Screen::new();
+
+        // The ROM only sets its bits once a probe has run, so a run that stops early reports
+        // what it saw up to there.
+        machine.load_builtin(BuiltinRom::QuirksTest).unwrap();
+        let _ = machine.run_headless(MAX_STEPS, &Keyboard::new(), &mut screen);
+        let mask = machine.register(5);
+
+        QuirkReport {
+            results: PROBES
+                .iter()
+                .map(|&(name, bit, configured)| QuirkResult {
+                    name,
+                    configured: configured(&quirks),
+                    detected: mask & bit != 0,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::Machine;
+    use crate::quirks::Quirks;
+
+    #[test]
+    fn test_quirk_report() {
+        for quirks in [Quirks::default(), Quirks::cosmac_vip()] {
+            let report = Machine::new(quirks).run_quirk_test();
+            assert_eq!(report.results.len(), 6);
+            assert!(report.passed(), "{report}");
+        }
+
+        let report = Machine::new(Quirks::cosmac_vip()).run_quirk_test();
+        let on: Vec<_> = report
+            .results
+            .iter()
+            .filter(|r| r.detected)
+            .map(|r| r.name)
+            .collect();
+        assert_eq!(
+            on,
+            [
+                "vf_reset_on_logic",
+                "shift_uses_vy",
+                "load_store_increments_i",
+                "clip_sprites"
+            ]
+        );
+        assert!(report
+            .to_string()
+            .starts_with("vf_reset_on_logic        configured on  detected on  pass\n"));
+    }
+}
@@ -0,0 +1,47 @@
+use serde::Deserialize;
+
+/// Behaviours that differ between CHIP-8 implementations.
+///
+/// The default matches what this emulator has always done, which is closest to SUPER-CHIP.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quirks {
+    /// 8xy6/8xyE shift Vy and store the result in Vx, instead of shifting Vx in place.
+    pub shift_uses_vy: bool,
+
+    /// Fx55/Fx65 leave I pointing past the last register transferred (I += x + 1).
+    pub load_store_increments_i: bool,
+
+    /// Bnnn is read as Bxnn and jumps to xnn + Vx instead of nnn + V0.
+    pub jump_with_vx: bool,
+
+    /// 8xy1/8xy2/8xy3 reset VF to 0.
+    pub vf_reset_on_logic: bool,
+
+    /// Fx1E sets VF to 1 when I + Vx goes past 0x0FFF and to 0 otherwise, like the Amiga
+    /// interpreter.
+    pub add_i_sets_vf: bool,
+
+    /// Dxyn waits for the next 60 Hz tick (the vertical blank) before the following
+    /// instruction runs, capping drawing at one sprite per frame.
+    pub display_wait: bool,
+
+    /// Dxyn drops sprite pixels past the right and bottom edges instead of wrapping them to
+    /// the other side. The sprite's starting position still wraps either way.
+    pub clip_sprites: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter, which most early games were written for.
+    pub const fn cosmac_vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_with_vx: false,
+            vf_reset_on_logic: true,
+            add_i_sets_vf: false,
+            display_wait: true,
+            clip_sprites: true,
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::render::Renderer;
+use crate::screen::Screen;
+use gif::{Encoder, EncodingError, Repeat};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Records what's drawn into an animated GIF.
+///
+/// Each `draw` is one display frame, so it should be called at the 60 Hz refresh rather than
+/// once per instruction. Identical consecutive frames are merged into one longer frame.
+pub struct GifRecorder {
+    scale: u32,
+    max_frames: usize,
+    ticks: usize,
+    frames: Vec<Frame>,
+}
+
+struct Frame {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    start: usize,
+    ticks: usize,
+}
+
+impl GifRecorder {
+    /// Records at most `max_frames` display frames, `max_frames / 60` seconds, with each
+    /// low resolution pixel drawn as a `scale` x `scale` square.
+    pub fn new(scale: u32, max_frames: usize) -> Self {
+        GifRecorder {
+            scale: scale.max(1),
+            max_frames,
+            ticks: 0,
+            frames: Vec::new(),
+        }
+    }
+
+    /// How many display frames have been recorded.
+    pub fn len(&self) -> usize {
+        self.ticks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks == 0
+    }
+
+    /// Whether the limit passed to `new` was reached, later frames are dropped.
+    pub fn is_full(&self) -> bool {
+        self.ticks >= self.max_frames
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), EncodingError> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    /// Encodes the recording. The canvas has the size of a low resolution screen, high
+    /// resolution frames are drawn at half the scale.
+    pub fn write<W: Write>(&self, w: W) -> Result<(), EncodingError> {
+        let (width, height) = (64 * self.scale as usize, 32 * self.scale as usize);
+        let mut encoder = Encoder::new(w, width as u16, height as u16, &[0, 0, 0, 255, 255, 255])?;
+        encoder.set_repeat(Repeat::Infinite)?;
+
+        for frame in &self.frames {
+            let mut buffer = Vec::with_capacity(width * height);
+            for y in 0..height {
+                let row = y * frame.height / height;
+                for x in 0..width {
+                    buffer.push(frame.pixels[row * frame.width + x * frame.width / width]);
+                }
+            }
+
+            encoder.write_frame(&gif::Frame {
+                width: width as u16,
+                height: height as u16,
+                delay: frame.delay(),
+                buffer: Cow::Owned(buffer),
+                ..gif::Frame::default()
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Frame {
+    /// The frame's delay in the GIF's 10ms units, rounded so that the frames stay in step with
+    /// 60 Hz over the whole recording.
+    fn delay(&self) -> u16 {
+        let centis = |tick: usize| (tick * 100 + 30) / 60;
+        (centis(self.start + self.ticks) - centis(self.start)) as u16
+    }
+}
+
+impl Renderer for GifRecorder {
+    fn draw(&mut self, screen: &Screen) {
+        if self.is_full() {
+            return;
+        }
+
+        let (width, height) = (screen.width(), screen.height());
+        let pixels: Vec<u8> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| screen.is_set(x, y) as u8)
+            .collect();
+
+        match self.frames.last_mut() {
+            Some(last) if last.width == width && last.pixels == pixels => last.ticks += 1,
+            _ => self.frames.push(Frame {
+                width,
+                height,
+                pixels,
+                start: self.ticks,
+                ticks: 1,
+            }),
+        }
+        self.ticks += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::recorder::GifRecorder;
+    use crate::render::Renderer;
+    use crate::screen::Screen;
+
+    #[test]
+    fn test_gif_recorder() {
+        let mut screen = Screen::new();
+        let mut recorder = GifRecorder::new(2, 5);
+
+        for _ in 0..3 {
+            recorder.draw(&screen);
+        }
+        screen.set(1, 0, 1);
+        for _ in 0..3 {
+            recorder.draw(&screen);
+        }
+        assert_eq!(recorder.len(), 5);
+        assert!(recorder.is_full());
+
+        let mut out = Vec::new();
+        recorder.write(&mut out).unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(out.as_slice()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (128, 64));
+
+        // Three blank frames merged into one, then two with the pixel lit. At 60 Hz the
+        // delays add up to 5/60 of a second.
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer[2], frame.buffer[3 + 128]));
+        }
+        assert_eq!(frames, vec![(5, 0, 0), (3, 1, 1)]);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::screen::Screen;
+use std::io::{self, Stdout, Write};
+
+/// Something that can show the CHIP-8 display, so the core doesn't depend on any one
+/// windowing library.
+pub trait Renderer {
+    fn draw(&mut self, screen: &Screen);
+}
+
+/// Draws nothing, for tests and headless runs.
+pub struct NullRenderer;
+
+impl Renderer for NullRenderer {
+    fn draw(&mut self, _screen: &Screen) {}
+}
+
+/// Draws to a terminal with ANSI escapes, one block character per pixel.
+///
+/// Only the rows inside the screen's dirty rectangle are repainted, so the caller should
+/// `clear_dirty` once a frame has been drawn.
+pub struct TerminalRenderer<W: Write> {
+    out: W,
+    size: Option<(usize, usize)>,
+}
+
+impl TerminalRenderer<Stdout> {
+    pub fn stdout() -> Self {
+        TerminalRenderer::new(io::stdout())
+    }
+}
+
+impl<W: Write> TerminalRenderer<W> {
+    pub fn new(out: W) -> Self {
+        TerminalRenderer { out, size: None }
+    }
+
+    fn repaint(&mut self, screen: &Screen) -> io::Result<()> {
+        let size = (screen.width(), screen.height());
+        let (x, y, width, height) = if self.size != Some(size) {
+            // First frame or a resolution change, start from a blank terminal.
+            self.size = Some(size);
+            write!(self.out, "\x1b[?25l\x1b[2J")?;
+            (0, 0, size.0, size.1)
+        } else {
+            match screen.dirty_rect() {
+                Some(rect) => rect,
+                None => return Ok(()),
+            }
+        };
+
+        for row in y..y + height {
+            write!(self.out, "\x1b[{};{}H", row + 1, x + 1)?;
+            let line: String = (x..x + width)
+                .map(|col| if screen.is_set(col, row) { '█' } else { ' ' })
+                .collect();
+            self.out.write_all(line.as_bytes())?;
+        }
+        write!(self.out, "\x1b[{};1H", size.1 + 1)?;
+
+        self.out.flush()
+    }
+}
+
+impl<W: Write> Renderer for TerminalRenderer<W> {
+    fn draw(&mut self, screen: &Screen) {
+        // There's nowhere better to report a broken terminal, the frame is just lost.
+        let _ = self.repaint(screen);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::render::{Renderer, TerminalRenderer};
+    use crate::screen::Screen;
+
+    #[test]
+    fn test_terminal_renderer() {
+        let mut screen = Screen::new();
+        screen.set(0, 0, 1);
+        screen.set(2, 0, 1);
+
+        let mut out = Vec::new();
+        let mut renderer = TerminalRenderer::new(&mut out);
+        renderer.draw(&screen);
+        screen.clear_dirty();
+
+        // Nothing changed, nothing is written.
+        renderer.draw(&screen);
+
+        screen.set(5, 3, 1);
+        renderer.draw(&screen);
+
+        let text = String::from_utf8(out).unwrap();
+        let (first, second) = text.split_at(text.find("\x1b[33;1H").unwrap() + 7);
+        assert!(first.starts_with("\x1b[?25l\x1b[2J\x1b[1;1H█ █ "));
+        assert_eq!(first.matches('█').count(), 2);
+        assert_eq!(second, "\x1b[4;6H█\x1b[33;1H");
+    }
+}
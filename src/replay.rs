@@ -0,0 +1,272 @@
+// Input recordings, for bug reports and tool-assisted runs.
+//
+// A recording is the RNG seed plus every key change, stamped with the cycle it was seen on.
+// Replaying one means seeding the RNG the same way and applying each change before the
+// instruction it was recorded at. The timers and the keyboard `tick` still follow the host's
+// clock, so ROMs that depend on them may drift.
+//
+// The file format is plain text:
+//
+// ```text
+// chip8-input 1
+// seed 42
+// 120 5 down
+// 135 5 up
+// ```
+
+use crate::keyboard::Keyboard;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+const HEADER: &str = "chip8-input 1";
+
+/// A key going down or up before the instruction at `cycle` ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub cycle: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputLog {
+    /// What the machine's RNG was seeded with, see `Machine::seed_rng`.
+    pub seed: u64,
+    /// In cycle order.
+    pub events: Vec<InputEvent>,
+}
+
+impl InputLog {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "{HEADER}")?;
+        writeln!(w, "seed {}", self.seed)?;
+        for event in &self.events {
+            let state = if event.pressed { "down" } else { "up" };
+            writeln!(w, "{} {:x} {state}", event.cycle, event.key)?;
+        }
+        w.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        InputLog::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read<R: BufRead>(r: R) -> io::Result<Self> {
+        let mut lines = r.lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            return Err(invalid("not an input recording"));
+        }
+
+        let seed = lines
+            .next()
+            .transpose()?
+            .as_deref()
+            .and_then(|line| line.strip_prefix("seed "))
+            .and_then(|seed| seed.parse().ok())
+            .ok_or_else(|| invalid("missing seed"))?;
+
+        let mut events = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            events.push(parse_event(&line).ok_or_else(|| invalid(&line))?);
+        }
+
+        Ok(InputLog { seed, events })
+    }
+}
+
+fn parse_event(line: &str) -> Option<InputEvent> {
+    let mut parts = line.split_whitespace();
+    let cycle = parts.next()?.parse().ok()?;
+    let key = u8::from_str_radix(parts.next()?, 16)
+        .ok()
+        .filter(|&k| k < 16)?;
+    let pressed = match parts.next()? {
+        "down" => true,
+        "up" => false,
+        _ => return None,
+    };
+
+    Some(InputEvent {
+        cycle,
+        key,
+        pressed,
+    })
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("bad input recording: {what}"),
+    )
+}
+
+/// Builds an `InputLog` by watching the keyboard the machine runs with.
+pub struct InputRecorder {
+    log: InputLog,
+    keys: [bool; 16],
+}
+
+impl InputRecorder {
+    /// `seed` must be what the machine's RNG was seeded with.
+    pub fn new(seed: u64) -> Self {
+        InputRecorder {
+            log: InputLog {
+                seed,
+                events: Vec::new(),
+            },
+            keys: [false; 16],
+        }
+    }
+
+    /// Notes every key that changed since the last call. Call before each step with the
+    /// machine's `cycles()`.
+    pub fn record(&mut self, cycle: u64, keyboard: &Keyboard) {
+        for key in 0..16 {
+            let pressed = keyboard.is_pressed(key);
+            if pressed != self.keys[key] {
+                self.keys[key] = pressed;
+                self.log.events.push(InputEvent {
+                    cycle,
+                    key: key as u8,
+                    pressed,
+                });
+            }
+        }
+    }
+
+    pub fn log(&self) -> &InputLog {
+        &self.log
+    }
+
+    pub fn finish(self) -> InputLog {
+        self.log
+    }
+}
+
+/// Presses and releases keys as an `InputLog` says.
+pub struct InputPlayer {
+    log: InputLog,
+    next: usize,
+}
+
+impl InputPlayer {
+    pub fn new(log: InputLog) -> Self {
+        InputPlayer { log, next: 0 }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.log.seed
+    }
+
+    /// Applies the events due by `cycle`. Call before each step with the machine's
+    /// `cycles()`.
+    pub fn apply(&mut self, cycle: u64, keyboard: &mut Keyboard) {
+        while let Some(event) = self.log.events.get(self.next) {
+            if event.cycle > cycle {
+                break;
+            }
+            if event.pressed {
+                keyboard.press(event.key as usize);
+            } else {
+                keyboard.release(event.key as usize);
+            }
+            self.next += 1;
+        }
+    }
+
+    /// Whether every event has been applied.
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.log.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keyboard::Keyboard;
+    use crate::machine::Machine;
+    use crate::quirks::Quirks;
+    use crate::replay::{InputEvent, InputLog, InputPlayer, InputRecorder};
+    use crate::screen::Screen;
+
+    // 200: 6105 - LD V1, 5
+    // 202: E19E - SKP V1
+    // 204: 1208 - JP 0x208
+    // 206: 7201 - ADD V2, 1
+    // 208: C3FF - RND V3, 0xFF
+    // 20A: 8334 - ADD V3, V3
+    // 20C: 1202 - JP 0x202
+    const PROGRAM: [u8; 14] = [
+        0x61, 0x05, 0xe1, 0x9e, 0x12, 0x08, 0x72, 0x01, 0xc3, 0xff, 0x83, 0x34, 0x12, 0x02,
+    ];
+
+    #[test]
+    fn test_record_and_replay() {
+        let keyboard_at = |cycle: u64, keyboard: &mut Keyboard| match cycle {
+            50 | 120 => keyboard.press(5),
+            80 | 130 => keyboard.release(5),
+            90 => keyboard.press(0xa),
+            _ => {}
+        };
+
+        let mut screen = Screen::new();
+        let mut keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.load(&PROGRAM).unwrap();
+        machine.seed_rng(7);
+        let mut recorder = InputRecorder::new(7);
+        for _ in 0..200 {
+            keyboard_at(machine.cycles(), &mut keyboard);
+            recorder.record(machine.cycles(), &keyboard);
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+        let recorded = machine.save_state(&screen);
+        assert!(machine.register(2) > 0);
+
+        let mut file = Vec::new();
+        recorder.finish().write(&mut file).unwrap();
+        let log = InputLog::read(file.as_slice()).unwrap();
+        assert_eq!(log.events.len(), 5);
+
+        let mut screen = Screen::new();
+        let mut keyboard = Keyboard::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.load(&PROGRAM).unwrap();
+        let mut player = InputPlayer::new(log);
+        machine.seed_rng(player.seed());
+        for _ in 0..200 {
+            player.apply(machine.cycles(), &mut keyboard);
+            machine.step(&keyboard, &mut screen).unwrap();
+        }
+
+        assert!(player.is_finished());
+        assert_eq!(machine.save_state(&screen), recorded);
+    }
+
+    #[test]
+    fn test_read() {
+        let log = InputLog::read("chip8-input 1\nseed 3\n10 f down\n12 f up\n".as_bytes()).unwrap();
+        assert_eq!(log.seed, 3);
+        assert_eq!(
+            log.events[0],
+            InputEvent {
+                cycle: 10,
+                key: 0xf,
+                pressed: true
+            }
+        );
+        assert!(!log.events[1].pressed);
+
+        assert!(InputLog::read("seed 3\n".as_bytes()).is_err());
+        assert!(InputLog::read("chip8-input 1\nseed 3\n10 10 down\n".as_bytes()).is_err());
+        assert!(InputLog::read("chip8-input 1\nseed 3\n10 1 sideways\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+// Small test programs bundled with the emulator, see `roms/README.md` for where they come
+// from.
+
+/// A ROM embedded in the library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinRom {
+    /// Draws the IBM logo and stops.
+    IbmLogo,
+    /// Runs a series of instruction checks and draws how many passed, A when all of them did.
+    /// A failure draws E followed by the number of checks passed before it.
+    OpcodeTest,
+    /// Shows the hex digit of each key pressed.
+    KeypadTest,
+    /// Detects which quirks the interpreter has and draws them as a number, the sum of 1
+    /// for `vf_reset_on_logic`, 2 for `shift_uses_vy`, 4 for `load_store_increments_i`, 8 for
+    /// `jump_with_vx`, 16 for `clip_sprites` and 32 for `add_i_sets_vf`.
+    QuirksTest,
+}
+
+impl BuiltinRom {
+    pub const ALL: [BuiltinRom; 4] = [
+        BuiltinRom::IbmLogo,
+        BuiltinRom::OpcodeTest,
+        BuiltinRom::KeypadTest,
+        BuiltinRom::QuirksTest,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinRom::IbmLogo => "ibm",
+            BuiltinRom::OpcodeTest => "opcodes",
+            BuiltinRom::KeypadTest => "keypad",
+            BuiltinRom::QuirksTest => "quirks",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        BuiltinRom::ALL.into_iter().find(|rom| rom.name() == name)
+    }
+
+    pub fn bytes(&self) -> &'static [u8] {
+        match self {
+            BuiltinRom::IbmLogo => include_bytes!("../roms/ibm_logo.ch8"),
+            BuiltinRom::OpcodeTest => include_bytes!("../roms/opcode_test.ch8"),
+            BuiltinRom::KeypadTest => include_bytes!("../roms/keypad_test.ch8"),
+            BuiltinRom::QuirksTest => include_bytes!("../roms/quirks_test.ch8"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keyboard::Keyboard;
+    use crate::machine::Machine;
+    use crate::quirks::Quirks;
+    use crate::roms::BuiltinRom;
+    use crate::screen::Screen;
+
+    #[test]
+    fn test_names() {
+        for rom in BuiltinRom::ALL {
+            assert_eq!(BuiltinRom::from_name(rom.name()), Some(rom));
+            assert!(!rom.bytes().is_empty());
+        }
+        assert_eq!(BuiltinRom::from_name("pong"), None);
+    }
+
+    #[test]
+    fn test_ibm_logo_frame() {
+        let mut screen = Screen::new();
+        let mut machine = Machine::new(Quirks::default());
+        machine.load_builtin(BuiltinRom::IbmLogo).unwrap();
+        machine
+            .run_headless(100, &Keyboard::new(), &mut screen)
+            .unwrap();
+
+        // The logo is finished well within 100 instructions, after that the ROM just spins.
+        assert!(screen.set_pixels().count() > 0);
+        assert_eq!(screen.frame_hash(), 0xd51c_f72d_af44_9159);
+    }
+}
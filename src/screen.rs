@@ -1,47 +1,322 @@
 // (0,0)	(63,0)
 // (0,31)	(63,31)
+//
+// In SUPER-CHIP high resolution mode the screen grows to 128x64.
+//
+// XO-CHIP adds more bit planes, each pixel holds one bit per plane. Drawing and clearing only
+// touch the selected planes, which is just the first one unless a ROM asks otherwise.
 
+use crate::palette::{to_rgb8, Palette};
+use image::{ImageResult, Rgb, RgbImage};
 use std::fmt::{Debug, Formatter};
+use std::path::Path;
+
+const LOW_RES: (usize, usize) = (64, 32);
+const HIGH_RES: (usize, usize) = (128, 64);
+
+/// Brightness of a lit pixel, switched off pixels fade from here down to 0.
+pub const FULL_BRIGHTNESS: u8 = 100;
+const DECAY_STEP: u8 = 25;
+
+/// How many bit planes a pixel has.
+pub const PLANES: usize = 4;
 
 pub struct Screen {
-    pixels: [[u8; 64]; 32],
+    width: usize,
+    height: usize,
+    // Both row by row, `width * height` long.
+    pixels: Vec<u8>,
+    brightness: Vec<u8>,
+    selected_planes: u8,
+    // Top left and bottom right corners of the pixels changed since the last `clear_dirty`.
+    dirty: Option<((usize, usize), (usize, usize))>,
+    // Pixels erased by the last sprite draw.
+    collisions: Vec<(usize, usize)>,
 }
 
 impl Screen {
     pub fn new() -> Self {
         Screen {
-            pixels: [[0; 64]; 32],
+            width: LOW_RES.0,
+            height: LOW_RES.1,
+            pixels: vec![0; LOW_RES.0 * LOW_RES.1],
+            brightness: vec![0; LOW_RES.0 * LOW_RES.1],
+            selected_planes: 1,
+            dirty: None,
+            collisions: Vec::new(),
         }
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_hires(&self) -> bool {
+        (self.width, self.height) == HIGH_RES
+    }
+
+    /// Switches between the 64x32 and 128x64 modes, clearing the display.
+    pub fn set_hires(&mut self, hires: bool) {
+        let (width, height) = if hires { HIGH_RES } else { LOW_RES };
+        self.set_resolution(width, height);
+    }
+
+    /// Resizes the display to `width` x `height`, clearing it.
+    pub fn set_resolution(&mut self, width: usize, height: usize) {
+        self.width = width.max(1);
+        self.height = height.max(1);
+        self.pixels = vec![0; self.width * self.height];
+        self.brightness = vec![0; self.width * self.height];
+        self.mark_all_dirty();
+    }
+
+    /// The planes drawing and clearing apply to, one bit per plane.
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & ((1 << PLANES) - 1);
+    }
+
+    /// Clears the selected planes.
     pub fn clear(&mut self) {
-        for i in 0..64 {
-            for j in 0..32 {
-                self.pixels[j][i] = 0;
+        for pixel in &mut self.pixels {
+            *pixel &= !self.selected_planes;
+        }
+        self.mark_all_dirty();
+    }
+
+    /// Moves the selected planes `n` pixels down, the rows scrolled in at the top are blank.
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+
+    /// Moves the selected planes 4 pixels left.
+    pub fn scroll_left(&mut self) {
+        self.scroll(-4, 0);
+    }
+
+    /// Moves the selected planes 4 pixels right.
+    pub fn scroll_right(&mut self) {
+        self.scroll(4, 0);
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let mask = self.selected_planes;
+        let before = self.pixels.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let moved = if (0..width).contains(&from_x) && (0..height).contains(&from_y) {
+                    before[self.index(from_x as usize, from_y as usize)] & mask
+                } else {
+                    0
+                };
+
+                let i = self.index(x as usize, y as usize);
+                self.pixels[i] = (self.pixels[i] & !mask) | moved;
+                if self.pixels[i] != 0 {
+                    self.brightness[i] = FULL_BRIGHTNESS;
+                }
             }
         }
+        self.mark_all_dirty();
     }
 
+    /// Sets the pixel in every selected plane.
     pub fn set(&mut self, x: usize, y: usize, bit: u8) {
-        self.pixels[y][x] = bit
+        for plane in 0..PLANES {
+            if self.selected_planes & (1 << plane) != 0 {
+                self.set_in_plane(plane, x, y, bit);
+            }
+        }
     }
 
-    pub fn get(&mut self, x: usize, y: usize) -> u8 {
-        if self.pixels[y][x] == 0 {
-            0
+    pub fn set_in_plane(&mut self, plane: usize, x: usize, y: usize, bit: u8) {
+        self.mark_dirty(x, y);
+        let i = self.index(x, y);
+        if bit != 0 {
+            self.pixels[i] |= 1 << plane;
+            self.brightness[i] = FULL_BRIGHTNESS;
         } else {
-            1
+            self.pixels[i] &= !(1 << plane);
+        }
+    }
+
+    /// The displayed brightness of a pixel, from 0 up to `FULL_BRIGHTNESS`.
+    ///
+    /// A pixel that was just switched off keeps glowing for a few frames, see `decay`.
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.brightness[self.index(x, y)]
+    }
+
+    /// Every pixel as `(x, y, brightness)`, row by row from the top left.
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, u8)> + '_ {
+        let width = self.width;
+        self.brightness
+            .iter()
+            .enumerate()
+            .map(move |(i, &brightness)| (i % width, i / width, brightness))
+    }
+
+    /// Like `pixels`, but only the ones with any brightness, the ones a renderer has to draw.
+    pub fn set_pixels(&self) -> impl Iterator<Item = (usize, usize, u8)> + '_ {
+        self.pixels().filter(|&(_, _, brightness)| brightness > 0)
+    }
+
+    /// Whether the pixel is logically on in any plane, regardless of how bright it's displayed.
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        self.pixels[self.index(x, y)] != 0
+    }
+
+    pub fn is_set_in_plane(&self, plane: usize, x: usize, y: usize) -> bool {
+        self.pixels[self.index(x, y)] & (1 << plane) != 0
+    }
+
+    /// The planes the pixel is on in, one bit per plane.
+    pub fn planes(&self, x: usize, y: usize) -> u8 {
+        self.pixels[self.index(x, y)]
+    }
+
+    /// The pixels the last Dxyn erased, the ones that set VF. Cleared by the next draw and by
+    /// `decay`, so an overlay built on it shows each collision for one frame.
+    pub fn last_collisions(&self) -> &[(usize, usize)] {
+        &self.collisions
+    }
+
+    /// Starts a sprite draw, forgetting the previous draw's collisions.
+    pub(crate) fn begin_sprite(&mut self) {
+        self.collisions.clear();
+    }
+
+    pub(crate) fn record_collision(&mut self, x: usize, y: usize) {
+        if !self.collisions.contains(&(x, y)) {
+            self.collisions.push((x, y));
+        }
+    }
+
+    /// Dims every switched off pixel one step, meant to be called once per rendered frame.
+    pub fn decay(&mut self) {
+        self.collisions.clear();
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let i = self.index(x, y);
+                if self.pixels[i] == 0 && self.brightness[i] > 0 {
+                    self.brightness[i] = self.brightness[i].saturating_sub(DECAY_STEP);
+                    self.mark_dirty(x, y);
+                }
+            }
         }
     }
+
+    /// Whether anything visible changed since the last `clear_dirty`, including fading.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    /// The smallest `(x, y, width, height)` rectangle covering every change since the last
+    /// `clear_dirty`, so a renderer can redraw just that region.
+    pub fn dirty_rect(&self) -> Option<(usize, usize, usize, usize)> {
+        self.dirty
+            .map(|((left, top), (right, bottom))| (left, top, right - left + 1, bottom - top + 1))
+    }
+
+    /// Call once the screen has been rendered.
+    pub fn clear_dirty(&mut self) {
+        self.dirty = None;
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel ({x}, {y}) is off screen"
+        );
+        y * self.width + x
+    }
+
+    fn mark_dirty(&mut self, x: usize, y: usize) {
+        self.dirty = Some(match self.dirty {
+            Some(((left, top), (right, bottom))) => {
+                ((left.min(x), top.min(y)), (right.max(x), bottom.max(y)))
+            }
+            None => ((x, y), (x, y)),
+        });
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.dirty = Some(((0, 0), (self.width() - 1, self.height() - 1)));
+    }
+
+    /// The logical pixels as an image in the colors of `palette`, each pixel blown up to a
+    /// `scale` x `scale` square.
+    pub fn to_image(&self, scale: u32, palette: &Palette) -> RgbImage {
+        let scale = scale.max(1);
+        let (width, height) = (self.width() as u32 * scale, self.height() as u32 * scale);
+
+        RgbImage::from_fn(width, height, |x, y| {
+            let planes = self.planes((x / scale) as usize, (y / scale) as usize);
+            Rgb(to_rgb8(palette.color(planes)))
+        })
+    }
+
+    /// Writes a screenshot to `path` as a PNG, see `to_image`.
+    pub fn to_png(&self, path: &Path, scale: u32, palette: &Palette) -> ImageResult<()> {
+        self.to_image(scale, palette)
+            .save_with_format(path, image::ImageFormat::Png)
+    }
+
+    /// A 64-bit FNV-1a hash of the resolution and the planes each pixel is on in, for comparing
+    /// frames against known-good ones in tests. Brightness and fading don't affect it.
+    pub fn frame_hash(&self) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let size = [self.width as u16, self.height as u16].map(u16::to_le_bytes);
+        size.iter()
+            .flatten()
+            .chain(&self.pixels)
+            .fold(OFFSET_BASIS, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(PRIME)
+            })
+    }
+
+    /// Copies the pixels out row by row, for save states. Each byte holds the pixel's planes.
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+
+    /// Restores pixels produced by `snapshot` at the current resolution.
+    pub fn restore(&mut self, pixels: &[u8]) {
+        for (pixel, saved) in self.pixels.iter_mut().zip(pixels) {
+            *pixel = *saved;
+        }
+        for (brightness, pixel) in self.brightness.iter_mut().zip(&self.pixels) {
+            *brightness = if *pixel != 0 { FULL_BRIGHTNESS } else { 0 };
+        }
+        self.mark_all_dirty();
+    }
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Debug for Screen {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut builder = String::new();
 
-        for j in 0..32 {
-            for i in 0..64 {
-                builder += if self.pixels[j][i] == 0 { "0" } else { "1" };
+        for j in 0..self.height() {
+            for i in 0..self.width() {
+                builder += &format!("{:x}", self.planes(i, j));
             }
 
             builder += "\n"
@@ -50,3 +325,241 @@ impl Debug for Screen {
         write!(f, "{:}", builder)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::palette::Palette;
+    use crate::screen::{Screen, FULL_BRIGHTNESS};
+
+    #[test]
+    fn test_get_does_not_mutate() {
+        let mut screen = Screen::new();
+        screen.set(3, 4, 1);
+
+        let before = format!("{:?}", screen);
+        assert_eq!(screen.get(3, 4), FULL_BRIGHTNESS);
+        assert!(screen.is_set(3, 4));
+        assert!(!screen.is_set(4, 3));
+        assert_eq!(format!("{:?}", screen), before);
+    }
+
+    #[test]
+    fn test_pixels() {
+        let mut screen = Screen::new();
+        screen.set(3, 4, 1);
+        screen.set(63, 31, 1);
+
+        let pixels: Vec<_> = screen.pixels().collect();
+        assert_eq!(pixels.len(), 64 * 32);
+        assert_eq!(pixels[0], (0, 0, 0));
+        assert_eq!(pixels[4 * 64 + 3], (3, 4, FULL_BRIGHTNESS));
+
+        let lit: Vec<_> = screen.set_pixels().collect();
+        assert_eq!(lit, [(3, 4, FULL_BRIGHTNESS), (63, 31, FULL_BRIGHTNESS)]);
+    }
+
+    #[test]
+    fn test_frame_hash() {
+        let mut a = Screen::new();
+        let mut b = Screen::new();
+        a.set(3, 4, 1);
+        b.set(3, 4, 1);
+        assert_eq!(a.frame_hash(), b.frame_hash());
+
+        // A switched off pixel still fading out doesn't count.
+        b.set(7, 8, 1);
+        b.set(7, 8, 0);
+        b.decay();
+        assert!(b.get(7, 8) > 0);
+        assert_eq!(a.frame_hash(), b.frame_hash());
+
+        b.set(5, 6, 1);
+        assert_ne!(a.frame_hash(), b.frame_hash());
+
+        // A blank display still differs by resolution.
+        let mut hires = Screen::new();
+        hires.set_hires(true);
+        assert_ne!(Screen::new().frame_hash(), hires.frame_hash());
+    }
+
+    #[test]
+    fn test_hires() {
+        let mut screen = Screen::new();
+        assert_eq!((screen.width(), screen.height()), (64, 32));
+
+        screen.set(10, 10, 1);
+        screen.set_hires(true);
+        assert_eq!((screen.width(), screen.height()), (128, 64));
+        assert!(!screen.is_set(10, 10));
+
+        screen.set(127, 63, 1);
+        assert!(screen.is_set(127, 63));
+        assert_eq!(format!("{:?}", screen).lines().count(), 64);
+
+        screen.set_hires(false);
+        assert_eq!((screen.width(), screen.height()), (64, 32));
+        assert!(!format!("{:?}", screen).contains('1'));
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut screen = Screen::new();
+        screen.set(1, 1, 1);
+        screen.set(2, 2, 1);
+        screen.set(2, 2, 0);
+
+        assert!(!screen.is_set(2, 2));
+        assert_eq!(screen.get(2, 2), FULL_BRIGHTNESS);
+
+        screen.decay();
+        assert_eq!(screen.get(1, 1), FULL_BRIGHTNESS);
+        assert!(screen.get(2, 2) < FULL_BRIGHTNESS);
+        assert!(screen.get(2, 2) > 0);
+
+        for _ in 0..10 {
+            screen.decay();
+        }
+        assert_eq!(screen.get(1, 1), FULL_BRIGHTNESS);
+        assert_eq!(screen.get(2, 2), 0);
+
+        screen.clear();
+        assert!(!screen.is_set(1, 1));
+        assert_eq!(screen.get(1, 1), FULL_BRIGHTNESS);
+    }
+
+    #[test]
+    fn test_planes() {
+        let mut screen = Screen::new();
+        assert_eq!(screen.selected_planes(), 1);
+
+        screen.set(0, 0, 1);
+        assert_eq!(screen.planes(0, 0), 0b01);
+
+        screen.select_planes(0b10);
+        screen.set(0, 0, 1);
+        screen.set(1, 0, 1);
+        assert_eq!(screen.planes(0, 0), 0b11);
+        assert_eq!(screen.planes(1, 0), 0b10);
+        assert!(screen.is_set_in_plane(1, 1, 0));
+        assert!(!screen.is_set_in_plane(0, 1, 0));
+
+        // Clearing leaves the unselected planes alone.
+        screen.clear();
+        assert_eq!(screen.planes(0, 0), 0b01);
+        assert!(!screen.is_set(1, 0));
+
+        screen.select_planes(0xff);
+        assert_eq!(screen.selected_planes(), 0b1111);
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut screen = Screen::new();
+        screen.set(10, 5, 1);
+        screen.set(62, 31, 1);
+
+        screen.scroll_down(3);
+        assert!(screen.is_set(10, 8));
+        assert!(!screen.is_set(10, 5));
+        // Pixels scrolled off the bottom are gone.
+        assert!(!(0..64).any(|x| screen.is_set(x, 31)));
+
+        screen.scroll_right();
+        assert!(screen.is_set(14, 8));
+        assert!(!screen.is_set(10, 8));
+
+        screen.scroll_left();
+        screen.scroll_left();
+        assert!(screen.is_set(6, 8));
+        assert!(!screen.is_set(14, 8));
+
+        screen.set(1, 0, 1);
+        screen.scroll_left();
+        assert!(!(0..32).any(|y| (60..64).any(|x| screen.is_set(x, y))));
+        assert!(!(0..4).any(|x| screen.is_set(x, 0)));
+        assert!(screen.is_set(2, 8));
+    }
+
+    #[test]
+    fn test_dirty() {
+        let mut screen = Screen::new();
+        assert!(!screen.is_dirty());
+
+        screen.set(3, 4, 1);
+        screen.set(10, 2, 1);
+        assert!(screen.is_dirty());
+        assert_eq!(screen.dirty_rect(), Some((3, 2, 8, 3)));
+
+        screen.clear_dirty();
+        assert!(!screen.is_dirty());
+        assert_eq!(screen.dirty_rect(), None);
+
+        // Lit pixels don't fade, so nothing changes.
+        screen.decay();
+        assert!(!screen.is_dirty());
+
+        screen.set(3, 4, 0);
+        screen.clear_dirty();
+        screen.decay();
+        assert_eq!(screen.dirty_rect(), Some((3, 4, 1, 1)));
+
+        screen.clear_dirty();
+        screen.scroll_down(1);
+        assert_eq!(screen.dirty_rect(), Some((0, 0, 64, 32)));
+    }
+
+    #[test]
+    fn test_to_png() {
+        let mut screen = Screen::new();
+        screen.set(0, 0, 1);
+        screen.set(63, 31, 1);
+
+        let path =
+            std::env::temp_dir().join(format!("chip8-screenshot-{}.png", std::process::id()));
+        screen
+            .to_png(&path, 4, &Palette::black_and_white())
+            .unwrap();
+        let image = image::open(&path).unwrap().to_rgb8();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(image.dimensions(), (256, 128));
+        assert_eq!(image.get_pixel(0, 0).0, [255, 255, 255]);
+        assert_eq!(image.get_pixel(3, 3).0, [255, 255, 255]);
+        assert_eq!(image.get_pixel(4, 0).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(255, 127).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut screen = Screen::new();
+        screen.set(63, 0, 1);
+        screen.set(0, 31, 1);
+        screen.clear();
+        assert!(!screen.is_set(63, 0));
+        assert!(!screen.is_set(0, 31));
+
+        screen.set_hires(true);
+        screen.set(127, 63, 1);
+        screen.set(100, 2, 1);
+        screen.clear();
+        assert!(!screen.is_set(127, 63));
+        assert!(!screen.is_set(100, 2));
+    }
+
+    #[test]
+    fn test_set_resolution() {
+        let mut screen = Screen::new();
+        screen.set(5, 5, 1);
+
+        screen.set_resolution(96, 48);
+        assert_eq!((screen.width(), screen.height()), (96, 48));
+        assert!(!screen.is_hires());
+        assert!(!screen.is_set(5, 5));
+
+        screen.set(95, 47, 1);
+        assert!(screen.is_set(95, 47));
+        assert_eq!(screen.snapshot().len(), 96 * 48);
+        assert_eq!(format!("{:?}", screen).lines().count(), 48);
+        assert_eq!(format!("{:?}", screen).lines().last().unwrap().len(), 96);
+    }
+}
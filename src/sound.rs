@@ -0,0 +1,185 @@
+/// Something that can play the CHIP-8 beeper tone.
+///
+/// The machine calls `start` when the sound timer becomes non-zero and `stop` when it runs out,
+/// so implementations don't have to care about the timer itself.
+pub trait Sound {
+    fn start(&mut self);
+    fn stop(&mut self);
+
+    /// Switches from the beeper tone to an XO-CHIP audio pattern played at `pitch`, see
+    /// `pattern_rate`. Called whenever either of them changes, the default ignores patterns.
+    fn set_pattern(&mut self, _pattern: &[u8; PATTERN_BYTES], _pitch: u8) {}
+
+    /// Goes back to the beeper tone.
+    fn clear_pattern(&mut self) {}
+}
+
+/// Size of the XO-CHIP audio pattern buffer, 128 one-bit samples.
+pub const PATTERN_BYTES: usize = 16;
+
+/// The pitch register's value after a reset, which plays patterns at 4000 samples per second.
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// Samples per second an XO-CHIP pattern plays at, an octave higher every 48 steps of pitch.
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+}
+
+/// Sample `i` of the pattern, the most significant bit of each byte first.
+pub fn pattern_bit(pattern: &[u8; PATTERN_BYTES], i: usize) -> bool {
+    let i = i % (PATTERN_BYTES * 8);
+    pattern[i / 8] >> (7 - i % 8) & 1 == 1
+}
+
+/// A silent backend, used by default and in headless runs.
+pub struct NullSound;
+
+impl Sound for NullSound {
+    fn start(&mut self) {}
+
+    fn stop(&mut self) {}
+}
+
+#[cfg(feature = "audio")]
+pub use beeper::Beeper;
+
+#[cfg(feature = "audio")]
+mod beeper {
+    use super::{pattern_bit, pattern_rate, Sound, PATTERN_BYTES};
+    use rodio::{OutputStream, OutputStreamHandle, Sink, Source};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const SAMPLE_RATE: u32 = 44100;
+    const FREQUENCY: u32 = 440;
+    const AMPLITUDE: f32 = 0.2;
+
+    type SharedPattern = Arc<Mutex<Option<([u8; PATTERN_BYTES], u8)>>>;
+
+    /// Plays a square wave, or the XO-CHIP pattern once one is set, through the default
+    /// output device via `rodio`.
+    pub struct Beeper {
+        sink: Sink,
+        pattern: SharedPattern,
+        _stream: OutputStream,
+        _handle: OutputStreamHandle,
+    }
+
+    impl Beeper {
+        pub fn new() -> Result<Self, String> {
+            let (stream, handle) = OutputStream::try_default().map_err(|e| e.to_string())?;
+            let sink = Sink::try_new(&handle).map_err(|e| e.to_string())?;
+            let pattern = SharedPattern::default();
+
+            sink.pause();
+            sink.append(Wave {
+                sample: 0,
+                position: 0.0,
+                pattern: pattern.clone(),
+            });
+
+            Ok(Beeper {
+                sink,
+                pattern,
+                _stream: stream,
+                _handle: handle,
+            })
+        }
+    }
+
+    impl Sound for Beeper {
+        fn start(&mut self) {
+            self.sink.play();
+        }
+
+        fn stop(&mut self) {
+            self.sink.pause();
+        }
+
+        fn set_pattern(&mut self, pattern: &[u8; PATTERN_BYTES], pitch: u8) {
+            *self.pattern.lock().unwrap() = Some((*pattern, pitch));
+        }
+
+        fn clear_pattern(&mut self) {
+            *self.pattern.lock().unwrap() = None;
+        }
+    }
+
+    struct Wave {
+        // Position in the square wave.
+        sample: u32,
+        // Position in the pattern, in pattern samples.
+        position: f32,
+        pattern: SharedPattern,
+    }
+
+    impl Iterator for Wave {
+        type Item = f32;
+
+        fn next(&mut self) -> Option<f32> {
+            let lit = match *self.pattern.lock().unwrap() {
+                Some((pattern, pitch)) => {
+                    let len = (PATTERN_BYTES * 8) as f32;
+                    self.position =
+                        (self.position + pattern_rate(pitch) / SAMPLE_RATE as f32) % len;
+                    pattern_bit(&pattern, self.position as usize)
+                }
+                None => {
+                    let period = SAMPLE_RATE / FREQUENCY;
+                    self.sample = (self.sample + 1) % period;
+                    self.sample < period / 2
+                }
+            };
+
+            if lit {
+                Some(AMPLITUDE)
+            } else {
+                Some(-AMPLITUDE)
+            }
+        }
+    }
+
+    impl Source for Wave {
+        fn current_frame_len(&self) -> Option<usize> {
+            None
+        }
+
+        fn channels(&self) -> u16 {
+            1
+        }
+
+        fn sample_rate(&self) -> u32 {
+            SAMPLE_RATE
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sound::{pattern_bit, pattern_rate, DEFAULT_PITCH};
+
+    #[test]
+    fn test_pattern_rate() {
+        assert_eq!(pattern_rate(DEFAULT_PITCH), 4000.0);
+        assert_eq!(pattern_rate(DEFAULT_PITCH + 48), 8000.0);
+        assert_eq!(pattern_rate(DEFAULT_PITCH - 48), 2000.0);
+    }
+
+    #[test]
+    fn test_pattern_bit() {
+        let mut pattern = [0; 16];
+        pattern[0] = 0b1000_0001;
+        pattern[15] = 0b0000_0001;
+
+        assert!(pattern_bit(&pattern, 0));
+        assert!(!pattern_bit(&pattern, 1));
+        assert!(pattern_bit(&pattern, 7));
+        assert!(pattern_bit(&pattern, 127));
+        // Wraps around, the pattern loops.
+        assert!(pattern_bit(&pattern, 128));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::keyboard::Keyboard;
+use crate::machine::Machine;
+use crate::palette::{to_rgb8, Palette};
+use crate::quirks::Quirks;
+use crate::screen::Screen;
+use wasm_bindgen::prelude::*;
+
+/// The machine, screen and keypad bundled up for JavaScript. The page drives it: a few
+/// `step`s and a `frame` per animation frame, see `www/index.js`.
+#[wasm_bindgen]
+pub struct Emulator {
+    machine: Machine,
+    screen: Screen,
+    keyboard: Keyboard,
+    palette: Palette,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Emulator {
+        Emulator {
+            machine: Machine::new(Quirks::default()),
+            screen: Screen::new(),
+            keyboard: Keyboard::new(),
+            palette: Palette::default(),
+        }
+    }
+
+    pub fn load(&mut self, rom: &[u8]) -> Result<(), JsError> {
+        self.machine
+            .load(rom)
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    pub fn set_xo_chip(&mut self, enabled: bool) {
+        self.machine.set_xo_chip(enabled);
+    }
+
+    /// Runs one instruction.
+    pub fn step(&mut self) -> Result<(), JsError> {
+        self.machine
+            .step(&self.keyboard, &mut self.screen)
+            .map(|_| ())
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Ends a 60 Hz frame: fades switched off pixels and latches key edges.
+    pub fn frame(&mut self) {
+        self.screen.decay();
+        self.keyboard.tick();
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.keyboard.press((key & 0xf) as usize);
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.keyboard.release((key & 0xf) as usize);
+    }
+
+    pub fn width(&self) -> usize {
+        self.screen.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.screen.height()
+    }
+
+    /// The display row by row, one byte per pixel holding the planes it's lit in. Arrives in
+    /// JavaScript as a `Uint8Array`.
+    pub fn screen(&self) -> Vec<u8> {
+        self.screen.snapshot()
+    }
+
+    /// The display as RGBA, ready for `ImageData`.
+    pub fn rgba(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.screen.width() * self.screen.height() * 4);
+        for (x, y, _) in self.screen.pixels() {
+            rgba.extend(to_rgb8(self.palette.pixel(&self.screen, x, y)));
+            rgba.push(0xff);
+        }
+        rgba
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,50 @@
+use chip8::keyboard::Keyboard;
+use chip8::machine::{Machine, StepOutcome};
+use chip8::quirks::Quirks;
+use chip8::roms::BuiltinRom;
+use chip8::screen::Screen;
+
+#[test]
+fn test_ibm_logo() {
+    let mut screen = Screen::new();
+    let keyboard = Keyboard::new();
+    let mut machine = Machine::new(Quirks::default());
+
+    machine.load_builtin(BuiltinRom::IbmLogo).unwrap();
+    // The ROM ends by jumping to itself.
+    let outcome = machine.run_headless(100, &keyboard, &mut screen).unwrap();
+    assert!(matches!(outcome, StepOutcome::Halted(_)));
+
+    // Top bar of the "I", drawn with its top left corner at (12, 8).
+    for x in 12..20 {
+        assert!(screen.is_set(x, 8));
+    }
+    assert!(!screen.is_set(11, 8));
+    assert!(!screen.is_set(20, 8));
+    // The gap between the stripes.
+    assert!(!screen.is_set(12, 9));
+    // The narrow stem.
+    assert!(!screen.is_set(13, 12));
+    assert!(screen.is_set(14, 12));
+    assert!(screen.is_set(17, 12));
+    assert!(!screen.is_set(18, 12));
+
+    // Nothing outside the logo.
+    assert!(!screen.is_set(0, 0));
+    assert!(!screen.is_set(63, 31));
+}
+
+#[test]
+fn test_opcode_rom() {
+    let mut screen = Screen::new();
+    let keyboard = Keyboard::new();
+    let mut machine = Machine::new(Quirks::default());
+
+    machine.load_builtin(BuiltinRom::OpcodeTest).unwrap();
+    let outcome = machine.run_headless(1000, &keyboard, &mut screen).unwrap();
+
+    // All ten checks passed and the count was drawn instead of the failure E.
+    assert_eq!(outcome, StepOutcome::Halted(0x2b6));
+    assert_eq!(machine.register(5), 10);
+    assert!(screen.is_set(0, 0));
+}
@@ -0,0 +1,168 @@
+//! Runs the bundled test ROMs under each quirk configuration and checks what they report.
+//!
+//! - `ibm` covers 00E0, 1nnn, 6xkk, 7xkk, Annn and Dxyn.
+//! - `opcodes` covers the 8xyN arithmetic and logic, the 3xkk/4xkk/5xy0/9xy0 skips, 2nnn/00EE,
+//!   Fx33, Fx55/Fx65 and Fx1E, counting the checks that pass.
+//! - `quirks` probes each behaviour in `Quirks` except `display_wait` and reports the ones it
+//!   found as a bitmask in V5.
+//!
+//! `display_wait` is left off everywhere: it holds each draw until the next 60 Hz tick of the
+//! wall clock, which would only make the runs slow and timing dependent.
+//!
+//! The community suites (Timendus' chip8-test-suite, corax+ and friends) aren't redistributable
+//! with the crate, so they aren't run here.
+
+use chip8::keyboard::Keyboard;
+use chip8::machine::{Machine, StepOutcome};
+use chip8::quirks::Quirks;
+use chip8::roms::BuiltinRom;
+use chip8::screen::Screen;
+
+const IBM_END: usize = 0x228;
+const OPCODES_END: usize = 0x2b6;
+const QUIRKS_END: usize = 0x26e;
+
+const ALL_QUIRKS: Quirks = Quirks {
+    shift_uses_vy: true,
+    load_store_increments_i: true,
+    jump_with_vx: true,
+    vf_reset_on_logic: true,
+    add_i_sets_vf: true,
+    display_wait: false,
+    clip_sprites: true,
+};
+
+const CONFIGS: [(&str, Quirks); 3] = [
+    (
+        "default",
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_with_vx: false,
+            vf_reset_on_logic: false,
+            add_i_sets_vf: false,
+            display_wait: false,
+            clip_sprites: false,
+        },
+    ),
+    (
+        "cosmac_vip",
+        Quirks {
+            display_wait: false,
+            ..Quirks::cosmac_vip()
+        },
+    ),
+    ("all", ALL_QUIRKS),
+];
+
+/// Runs `rom` until it jumps to itself.
+fn run(rom: BuiltinRom, quirks: Quirks) -> (Machine, Screen, StepOutcome) {
+    let mut screen = Screen::new();
+    let keyboard = Keyboard::new();
+    let mut machine = Machine::new(quirks);
+
+    machine.load_builtin(rom).unwrap();
+    let outcome = machine.run_headless(1000, &keyboard, &mut screen).unwrap();
+    (machine, screen, outcome)
+}
+
+#[test]
+fn test_ibm_under_every_config() {
+    for (name, quirks) in CONFIGS {
+        let (_, screen, outcome) = run(BuiltinRom::IbmLogo, quirks);
+        assert_eq!(outcome, StepOutcome::Halted(IBM_END), "{}", name);
+        assert!(screen.is_set(12, 8), "{}", name);
+    }
+}
+
+#[test]
+fn test_opcodes_under_every_config() {
+    for (name, quirks) in CONFIGS {
+        let (machine, screen, outcome) = run(BuiltinRom::OpcodeTest, quirks);
+        assert_eq!(outcome, StepOutcome::Halted(OPCODES_END), "{}", name);
+        assert_eq!(machine.register(5), 10, "{}: checks passed", name);
+        assert!(screen.is_set(0, 0), "{}", name);
+    }
+}
+
+#[test]
+fn test_quirks_detected() {
+    let expected = [("default", 0), ("cosmac_vip", 1 | 2 | 4 | 16), ("all", 63)];
+
+    for ((name, quirks), (_, mask)) in CONFIGS.into_iter().zip(expected) {
+        let (machine, _, outcome) = run(BuiltinRom::QuirksTest, quirks);
+        assert_eq!(outcome, StepOutcome::Halted(QUIRKS_END), "{}", name);
+        assert_eq!(machine.register(5), mask, "{}", name);
+    }
+}
+
+#[test]
+fn test_each_quirk_alone() {
+    let single = [
+        (
+            1,
+            Quirks {
+                vf_reset_on_logic: true,
+                ..Quirks::default()
+            },
+        ),
+        (
+            2,
+            Quirks {
+                shift_uses_vy: true,
+                ..Quirks::default()
+            },
+        ),
+        (
+            4,
+            Quirks {
+                load_store_increments_i: true,
+                ..Quirks::default()
+            },
+        ),
+        (
+            8,
+            Quirks {
+                jump_with_vx: true,
+                ..Quirks::default()
+            },
+        ),
+        (
+            16,
+            Quirks {
+                clip_sprites: true,
+                ..Quirks::default()
+            },
+        ),
+        (
+            32,
+            Quirks {
+                add_i_sets_vf: true,
+                ..Quirks::default()
+            },
+        ),
+    ];
+
+    for (mask, quirks) in single {
+        let (machine, _, outcome) = run(BuiltinRom::QuirksTest, quirks);
+        assert_eq!(outcome, StepOutcome::Halted(QUIRKS_END), "{:?}", quirks);
+        assert_eq!(machine.register(5), mask, "{:?}", quirks);
+    }
+}
+
+#[test]
+fn test_quirks_result_frames() {
+    // The mask is drawn in decimal, so each distinct result is a distinct frame.
+    let hashes: Vec<u64> = CONFIGS
+        .into_iter()
+        .map(|(_, quirks)| run(BuiltinRom::QuirksTest, quirks).1.frame_hash())
+        .collect();
+    assert_eq!(
+        hashes,
+        [
+            0xa88f_a719_ce9b_449b,
+            0x968e_3a34_8309_5c3d,
+            0x4eb7_f165_2dc2_dbf6
+        ]
+    );
+}
@@ -0,0 +1,59 @@
+// Build the package first, from the repository root:
+//   wasm-pack build --target web --out-dir www/pkg --no-default-features --features wasm
+// then serve this directory over HTTP.
+import init, { Emulator } from "./pkg/chip8.js";
+
+// Instructions per frame, 540 Hz at 60 frames a second like the desktop build.
+const STEPS_PER_FRAME = 9;
+
+// The left side of a QWERTY keyboard stands in for the hex keypad, as on the desktop.
+const KEYS = {
+  Digit1: 0x1, Digit2: 0x2, Digit3: 0x3, Digit4: 0xc,
+  KeyQ: 0x4, KeyW: 0x5, KeyE: 0x6, KeyR: 0xd,
+  KeyA: 0x7, KeyS: 0x8, KeyD: 0x9, KeyF: 0xe,
+  KeyZ: 0xa, KeyX: 0x0, KeyC: 0xb, KeyV: 0xf,
+};
+
+await init();
+
+const canvas = document.getElementById("screen");
+const context = canvas.getContext("2d");
+let emulator = null;
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+  const rom = new Uint8Array(await event.target.files[0].arrayBuffer());
+  emulator = new Emulator();
+  emulator.load(rom);
+});
+
+document.addEventListener("keydown", (event) => {
+  if (emulator && event.code in KEYS) emulator.press(KEYS[event.code]);
+});
+document.addEventListener("keyup", (event) => {
+  if (emulator && event.code in KEYS) emulator.release(KEYS[event.code]);
+});
+
+function frame() {
+  if (emulator) {
+    for (let i = 0; i < STEPS_PER_FRAME; i++) {
+      try {
+        emulator.step();
+      } catch (e) {
+        console.error(e);
+      }
+    }
+
+    const width = emulator.width();
+    const height = emulator.height();
+    if (canvas.width !== width || canvas.height !== height) {
+      canvas.width = width;
+      canvas.height = height;
+    }
+    const pixels = new Uint8ClampedArray(emulator.rgba());
+    context.putImageData(new ImageData(pixels, width, height), 0, 0);
+    emulator.frame();
+  }
+  requestAnimationFrame(frame);
+}
+
+requestAnimationFrame(frame);